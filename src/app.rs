@@ -0,0 +1,1403 @@
+use crate::backup::{BackupOptions, BackupStore, JobRecord};
+use crate::export::{self, ReportFormat};
+use crate::job::JobSpec;
+use crate::output::{ConflictStrategy, OutputMode, OutputPolicy};
+use crate::pipeline::{Pipeline, StepSpec};
+use crate::preview_view::{PreviewAction, PreviewView};
+use crate::processing_thread::{FileProcessingThread, ProgressEvent};
+use crate::processor::{self, FileProcessor};
+use crate::results::{FileResult, ResultStatus};
+use crate::results_view::ResultsView;
+use crate::retry::RetryPolicy;
+use crate::scan::{DirectoryScanner, ScanOptions};
+use crate::watch::{FolderWatcher, WatchOptions};
+use crate::watch_view::{WatchAction, WatchView};
+use crate::worker_pool::WorkerPoolOptions;
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Timeout suggested when the per-file timeout is first switched on.
+const DEFAULT_FILE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Attempts suggested when retrying transient failures is first switched on.
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+const SECS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+const MIB: u64 = 1024 * 1024;
+
+pub struct MyApp {
+    dropped_files: HashSet<PathBuf>,
+    dropped_folders: BTreeMap<PathBuf, DroppedFolder>,
+    scanner: DirectoryScanner,
+    scan_options: ScanOptions,
+    include_patterns: String,
+    exclude_patterns: String,
+    file_processing_thread: FileProcessingThread,
+    pool_options: WorkerPoolOptions,
+    file_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    output_policy: OutputPolicy,
+    backup_options: BackupOptions,
+    /// The job "Undo last job" would undo, as of the last time we looked.
+    last_job: Option<JobRecord>,
+    repaint_ctx: egui::Context,
+    processors: Vec<Arc<dyn FileProcessor>>,
+    /// The steps every file goes through, a single one unless a pipeline was built.
+    pipeline: Vec<StepSpec>,
+    processing_btn_enabled: bool,
+    result_msg: String,
+    results_view: ResultsView,
+    /// Changes of the last dry run, waiting to be accepted or rejected.
+    preview_view: PreviewView,
+    /// The engine set up like the dry run shown in `preview_view`, which
+    /// applies the accepted changes.
+    preview_engine: Option<FileProcessingThread>,
+    /// Results of the job the running "Retry failed" job will be merged into.
+    retry_base: Option<Vec<FileResult>>,
+    /// The last finished job, set up to run again for "Retry failed".
+    last_engine: Option<FileProcessingThread>,
+    watch_view: WatchView,
+    notice: Option<String>,
+    file_status: HashMap<PathBuf, FileStatus>,
+    job_progress: Option<JobProgress>,
+    /// Files that timed out but whose processor hasn't returned yet.
+    overdue_files: BTreeSet<PathBuf>,
+}
+
+/// The part of `MyApp` that survives a restart.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PersistedState {
+    dropped_files: Vec<PathBuf>,
+    dropped_folders: Vec<PathBuf>,
+    scan_options: ScanOptions,
+    pool_options: WorkerPoolOptions,
+    file_timeout: Option<Duration>,
+    retry_policy: RetryPolicy,
+    output_policy: OutputPolicy,
+    backup_options: BackupOptions,
+    watch_options: WatchOptions,
+    pipeline: Vec<StepSpec>,
+    results: Vec<FileResult>,
+}
+
+/// A change to the pipeline requested by the buttons of one of its steps.
+enum StepAction {
+    MoveUp(usize),
+    MoveDown(usize),
+    Remove(usize),
+}
+
+/// A dropped directory together with the files found below it.
+#[derive(Default)]
+struct DroppedFolder {
+    files: Vec<PathBuf>,
+    scanning: bool,
+    error: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FileStatus {
+    Pending,
+    /// Bytes done and total size, both zero until the processor reports progress.
+    Running {
+        done: u64,
+        total: u64,
+    },
+    Succeeded(Duration),
+    Failed(Duration),
+    TimedOut(Duration),
+    Cancelled,
+}
+
+impl FileStatus {
+    fn icon(&self) -> String {
+        let icon = match self {
+            FileStatus::Running { total: 0, .. } => "⏳",
+            FileStatus::Running { done, total } => {
+                return format!("⏳ {}%", done * 100 / total);
+            }
+            FileStatus::Pending => "⏸",
+            FileStatus::Succeeded(_) => "✔",
+            FileStatus::Failed(_) => "⚠",
+            FileStatus::TimedOut(_) => "⌛",
+            FileStatus::Cancelled => "⏹",
+        };
+        icon.to_owned()
+    }
+
+    fn hover_text(&self) -> String {
+        match self {
+            FileStatus::Pending => String::from("Waiting"),
+            FileStatus::Running { total: 0, .. } => String::from("Processing"),
+            FileStatus::Running { done, total } => {
+                format!("Processing, {done} of {total} bytes done")
+            }
+            FileStatus::Succeeded(duration) => format!("Succeeded in {duration:.2?}"),
+            FileStatus::Failed(duration) => format!("Failed after {duration:.2?}"),
+            FileStatus::TimedOut(duration) => format!("Timed out after {duration:.2?}"),
+            FileStatus::Cancelled => String::from("Cancelled"),
+        }
+    }
+}
+
+struct JobProgress {
+    total: usize,
+    finished: usize,
+    started_at: Instant,
+}
+
+impl JobProgress {
+    fn new(total: usize) -> Self {
+        JobProgress {
+            total,
+            finished: 0,
+            started_at: Instant::now(),
+        }
+    }
+
+    fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.finished as f32 / self.total as f32
+        }
+    }
+
+    fn eta(&self) -> Option<Duration> {
+        if self.finished == 0 {
+            return None;
+        }
+        let per_file = self.started_at.elapsed() / self.finished as u32;
+        Some(per_file * (self.total - self.finished) as u32)
+    }
+}
+
+impl Default for MyApp {
+    fn default() -> Self {
+        let processors = processor::builtin_processors();
+        MyApp {
+            dropped_files: HashSet::new(),
+            dropped_folders: BTreeMap::new(),
+            scanner: DirectoryScanner::new(),
+            scan_options: ScanOptions::default(),
+            include_patterns: String::new(),
+            exclude_patterns: String::new(),
+            file_processing_thread: FileProcessingThread::new(processors[0].clone()),
+            pool_options: WorkerPoolOptions::default(),
+            file_timeout: None,
+            retry_policy: RetryPolicy::default(),
+            output_policy: OutputPolicy::default(),
+            backup_options: BackupOptions::default(),
+            last_job: None,
+            repaint_ctx: egui::Context::default(),
+            pipeline: vec![StepSpec::new(processors[0].name())],
+            processors,
+            processing_btn_enabled: true,
+            result_msg: String::new(),
+            results_view: ResultsView::new(),
+            preview_view: PreviewView::new(),
+            preview_engine: None,
+            retry_base: None,
+            last_engine: None,
+            watch_view: WatchView::new(),
+            notice: None,
+            file_status: HashMap::new(),
+            job_progress: None,
+            overdue_files: BTreeSet::new(),
+        }
+    }
+}
+
+impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let state = PersistedState {
+            dropped_files: self.dropped_files.iter().cloned().collect(),
+            dropped_folders: self.dropped_folders.keys().cloned().collect(),
+            scan_options: self.scan_options.clone(),
+            pool_options: self.pool_options,
+            file_timeout: self.file_timeout,
+            retry_policy: self.retry_policy,
+            output_policy: self.output_policy.clone(),
+            backup_options: self.backup_options,
+            watch_options: self.watch_view.options,
+            pipeline: self.pipeline.clone(),
+            results: self.results_view.results().to_vec(),
+        };
+        eframe::set_value(storage, eframe::APP_KEY, &state);
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_progress();
+        self.update_scans();
+        if self.file_processing_thread.is_finished() {
+            self.gather_processing_results();
+        }
+        self.update_watch(ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label("Drag-and-drop files or folders onto the window");
+            ui.horizontal(|ui| {
+                if ui.button("Add files…").clicked() {
+                    self.pick_files();
+                }
+                if ui.button("Add folder…").clicked() {
+                    self.pick_folder();
+                }
+            });
+            if let Some(notice) = &self.notice {
+                ui.colored_label(ui.visuals().warn_fg_color, notice);
+            }
+            self.draw_scan_options(ui);
+            self.draw_pool_options(ui);
+            self.draw_output_options(ui);
+            self.draw_watch(ui);
+
+            let central_panel_rect = ui.available_rect_before_wrap();
+
+            if self.dropped_files.is_empty() && self.dropped_folders.is_empty() {
+                return;
+            }
+
+            egui::containers::ScrollArea::vertical()
+                .max_height(central_panel_rect.height() / 2.0)
+                .max_width(central_panel_rect.width())
+                .show(ui, |ui| {
+                    self.draw_files_list(ui);
+                });
+
+            let scanning = self.dropped_folders.values().any(|f| f.scanning);
+            ui.add_enabled_ui(
+                self.processing_btn_enabled && !scanning,
+                |ui: &mut egui::Ui| {
+                    ui.group(|ui| self.draw_pipeline_editor(ui));
+
+                    ui.horizontal(|ui| {
+                        let prcocess_btn = ui.button("Process");
+                        if prcocess_btn.clicked() {
+                            self.start_processing_files(false);
+                        };
+                        let preview_btn = ui
+                            .button("Preview changes")
+                            .on_hover_text("Dry run showing what would change without writing");
+                        if preview_btn.clicked() {
+                            self.start_processing_files(true);
+                        }
+                    });
+                },
+            );
+
+            if !self.processing_btn_enabled {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    self.draw_progress_bar(ui);
+
+                    let cancel_requested = self.file_processing_thread.is_cancel_requested();
+                    let cancel_btn = ui.add_enabled(!cancel_requested, egui::Button::new("Cancel"));
+                    if cancel_btn.clicked() {
+                        self.file_processing_thread.cancel();
+                    }
+                });
+                self.draw_overdue_files(ui);
+            }
+
+            let mut preview_action = None;
+            egui::containers::ScrollArea::vertical()
+                .max_height(central_panel_rect.height() / 2.0)
+                .max_width(central_panel_rect.width())
+                .id_source("output scroll area")
+                .show(ui, |ui| {
+                    if !self.result_msg.is_empty() {
+                        ui.label(&self.result_msg);
+                    }
+                    if !self.preview_view.is_empty() {
+                        ui.group(|ui| {
+                            preview_action =
+                                self.preview_view.show(ui, self.processing_btn_enabled);
+                        });
+                    }
+                    if !self.results_view.is_empty() {
+                        self.results_view.show(ui);
+                    }
+                });
+            match preview_action {
+                Some(PreviewAction::Apply(files)) => self.apply_preview(files),
+                Some(PreviewAction::Discard) => self.clear_preview(),
+                None => {}
+            }
+
+            ui.horizontal(|ui| {
+                let save_btn = ui.add_enabled(
+                    !self.results_view.is_empty(),
+                    egui::Button::new("Save report as…"),
+                );
+                if save_btn.clicked() {
+                    self.save_report();
+                }
+
+                let failed = self.failed_files();
+                let retry_btn = ui
+                    .add_enabled(
+                        self.processing_btn_enabled
+                            && self.last_engine.is_some()
+                            && !failed.is_empty(),
+                        egui::Button::new("Retry failed"),
+                    )
+                    .on_hover_text("Process only the files that failed or panicked again")
+                    .on_disabled_hover_text(if self.last_engine.is_some() {
+                        "No failed files to retry"
+                    } else {
+                        "Only files that failed since the start can be retried"
+                    });
+                if retry_btn.clicked() {
+                    self.retry_failed(failed);
+                }
+
+                let undo_hover = match &self.last_job {
+                    Some(job) => format!(
+                        "Restore the {} file(s) written by {} at {}",
+                        job.files().len(),
+                        job.name,
+                        humantime::format_rfc3339_seconds(job.started)
+                    ),
+                    None => String::from("No job with backups to undo"),
+                };
+                let undo_btn = ui
+                    .add_enabled(
+                        self.processing_btn_enabled && self.last_job.is_some(),
+                        egui::Button::new("Undo last job"),
+                    )
+                    .on_hover_text(&undo_hover)
+                    .on_disabled_hover_text(undo_hover);
+                if undo_btn.clicked() {
+                    self.undo_last_job();
+                }
+            });
+        });
+
+        // Collect dropped files:
+        ctx.input(|i| {
+            if !i.raw.dropped_files.is_empty() {
+                let file_paths: Vec<_> = i
+                    .raw
+                    .dropped_files
+                    .iter()
+                    .filter_map(|p| p.path.clone())
+                    .collect();
+                self.add_paths(file_paths);
+            }
+
+            if self.dropped_files.is_empty() && self.dropped_folders.is_empty() {
+                self.result_msg = String::new();
+                self.results_view.clear();
+            }
+        });
+    }
+}
+
+impl MyApp {
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = MyApp {
+            repaint_ctx: cc.egui_ctx.clone(),
+            scanner: DirectoryScanner::new().with_repaint_context(cc.egui_ctx.clone()),
+            ..Default::default()
+        };
+        if let Some(state) = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+        {
+            app.restore(state);
+        }
+        app.refresh_last_job();
+        app
+    }
+
+    /// Applies state saved by a previous session, skipping paths that no longer exist.
+    fn restore(&mut self, state: PersistedState) {
+        let mut missing = vec![];
+        let mut keep_existing = |path: &PathBuf| {
+            let exists = path.exists();
+            if !exists && !missing.contains(path) {
+                missing.push(path.clone());
+            }
+            exists
+        };
+
+        let dropped_files: Vec<_> = state
+            .dropped_files
+            .into_iter()
+            .filter(|p| keep_existing(p))
+            .collect();
+        let dropped_folders: Vec<_> = state
+            .dropped_folders
+            .into_iter()
+            .filter(|p| keep_existing(p))
+            .collect();
+        let results: Vec<_> = state
+            .results
+            .into_iter()
+            .filter(|r| keep_existing(&r.path))
+            .collect();
+
+        self.include_patterns = state.scan_options.include.join(", ");
+        self.exclude_patterns = state.scan_options.exclude.join(", ");
+        self.scan_options = state.scan_options;
+        self.pool_options = state.pool_options;
+        self.file_timeout = state.file_timeout;
+        self.retry_policy = state.retry_policy;
+        self.output_policy = state.output_policy;
+        self.backup_options = state.backup_options;
+        self.watch_view.options = state.watch_options;
+        if !state.pipeline.is_empty() {
+            self.pipeline = state.pipeline;
+        }
+
+        self.dropped_files.extend(dropped_files);
+        for folder in dropped_folders {
+            self.scan_folder(folder);
+        }
+        if !results.is_empty() {
+            self.results_view.set_results(results);
+            self.result_msg = self.results_summary();
+        }
+
+        if !missing.is_empty() {
+            let missing: Vec<_> = missing.iter().map(|p| p.display().to_string()).collect();
+            self.notice = Some(format!(
+                "Removed {} saved path(s) that no longer exist:\n{}",
+                missing.len(),
+                missing.join("\n")
+            ));
+        }
+    }
+
+    /// Adds dropped paths, sending directories off to be scanned and loading
+    /// job files instead of adding them.
+    fn add_paths(&mut self, paths: Vec<PathBuf>) {
+        for path in paths {
+            if path.is_dir() {
+                self.scan_folder(path);
+            } else if JobSpec::is_job_file(&path) {
+                self.load_job(&path);
+            } else {
+                self.dropped_files.insert(path);
+            }
+        }
+    }
+
+    fn pick_files(&mut self) {
+        if let Some(files) = rfd::FileDialog::new().set_title("Add files").pick_files() {
+            self.add_paths(files);
+        }
+    }
+
+    fn pick_folder(&mut self) {
+        if let Some(folder) = rfd::FileDialog::new().set_title("Add folder").pick_folder() {
+            self.scan_folder(folder);
+        }
+    }
+
+    fn save_report(&mut self) {
+        let mut dialog = rfd::FileDialog::new()
+            .set_title("Save report as")
+            .set_file_name("report.json");
+        for format in ReportFormat::ALL {
+            dialog = dialog.add_filter(format.name(), &[format.extension()]);
+        }
+        let Some(mut path) = dialog.save_file() else {
+            return;
+        };
+
+        let format = ReportFormat::from_path(&path).unwrap_or_else(|| {
+            path.set_extension(ReportFormat::Json.extension());
+            ReportFormat::Json
+        });
+        self.notice = match export::save_report(self.results_view.results(), format, &path) {
+            Ok(()) => None,
+            Err(e) => Some(format!("Failed to save report: {e:#}")),
+        };
+    }
+
+    fn load_job(&mut self, path: &Path) {
+        self.notice = match JobSpec::load(path) {
+            Ok(spec) => {
+                self.apply_job(spec);
+                Some(format!("Loaded job {}", path.display()))
+            }
+            Err(e) => Some(format!("Failed to load job: {e:#}")),
+        };
+    }
+
+    /// Takes over the pipeline, filters and worker settings of `spec`.
+    fn apply_job(&mut self, spec: JobSpec) {
+        self.pipeline = spec.steps();
+        self.scan_options = spec.scan_options();
+        self.include_patterns = self.scan_options.include.join(", ");
+        self.exclude_patterns = self.scan_options.exclude.join(", ");
+        self.pool_options = spec.pool_options();
+        self.file_timeout = spec.timeout;
+        self.retry_policy = spec.retry_policy();
+        self.output_policy = spec.output;
+        // The filters may have changed
+        let roots: Vec<_> = self.dropped_folders.keys().cloned().collect();
+        for root in roots {
+            self.scan_folder(root);
+        }
+    }
+
+    fn pick_job(&mut self) {
+        if let Some(path) = rfd::FileDialog::new()
+            .set_title("Load job")
+            .add_filter("Job", &["toml"])
+            .pick_file()
+        {
+            self.load_job(&path);
+        }
+    }
+
+    fn save_job(&mut self) {
+        let Some(mut path) = rfd::FileDialog::new()
+            .set_title("Save job as")
+            .set_file_name("job.toml")
+            .add_filter("Job", &["toml"])
+            .save_file()
+        else {
+            return;
+        };
+        if !JobSpec::has_job_extension(&path) {
+            path.set_extension("toml");
+        }
+        let spec = JobSpec::new(self.pipeline_steps())
+            .with_scan_options(&self.scan_options)
+            .with_pool_options(self.pool_options)
+            .with_timeout(self.file_timeout)
+            .with_retry_policy(self.retry_policy)
+            .with_output_policy(self.output_policy.clone());
+        self.notice = match spec.save(&path) {
+            Ok(()) => None,
+            Err(e) => Some(format!("Failed to save job: {e:#}")),
+        };
+    }
+
+    fn scan_folder(&mut self, root: PathBuf) {
+        self.dropped_folders.insert(
+            root.clone(),
+            DroppedFolder {
+                scanning: true,
+                ..Default::default()
+            },
+        );
+        self.scanner.scan(root, self.scan_options.clone());
+    }
+
+    fn update_scans(&mut self) {
+        for result in self.scanner.poll_results() {
+            // The folder may have been removed while it was being scanned
+            let Some(folder) = self.dropped_folders.get_mut(&result.root) else {
+                continue;
+            };
+            folder.scanning = false;
+            match result.files {
+                Ok(files) => {
+                    folder.files = files;
+                    folder.error = None;
+                }
+                Err(e) => folder.error = Some(format!("{e:#}")),
+            }
+        }
+    }
+
+    /// All files that would be processed: loose files plus the contents of every folder.
+    fn files_to_process(&self) -> Vec<PathBuf> {
+        let mut files: Vec<_> = self.dropped_files.iter().cloned().collect();
+        let mut seen: HashSet<_> = self.dropped_files.clone();
+        for folder in self.dropped_folders.values() {
+            for file in &folder.files {
+                if seen.insert(file.clone()) {
+                    files.push(file.clone());
+                }
+            }
+        }
+        files
+    }
+
+    fn draw_scan_options(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Folder scan options").show(ui, |ui| {
+            egui::Grid::new("scan options grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Max depth");
+                    ui.horizontal(|ui| {
+                        let mut limited = self.scan_options.max_depth.is_some();
+                        ui.checkbox(&mut limited, "Limit");
+                        if limited {
+                            let depth = self.scan_options.max_depth.get_or_insert(1);
+                            ui.add(egui::DragValue::new(depth).clamp_range(1..=64));
+                        } else {
+                            self.scan_options.max_depth = None;
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Symlinks");
+                    ui.checkbox(&mut self.scan_options.follow_symlinks, "Follow");
+                    ui.end_row();
+
+                    ui.label("Include");
+                    if ui
+                        .text_edit_singleline(&mut self.include_patterns)
+                        .on_hover_text("Glob patterns such as *.txt, separated by commas")
+                        .changed()
+                    {
+                        self.scan_options.include =
+                            ScanOptions::parse_patterns(&self.include_patterns);
+                    }
+                    ui.end_row();
+
+                    ui.label("Exclude");
+                    if ui
+                        .text_edit_singleline(&mut self.exclude_patterns)
+                        .on_hover_text("Glob patterns such as target/**, separated by commas")
+                        .changed()
+                    {
+                        self.scan_options.exclude =
+                            ScanOptions::parse_patterns(&self.exclude_patterns);
+                    }
+                    ui.end_row();
+                });
+
+            let rescan_btn = ui.add_enabled(
+                !self.dropped_folders.is_empty(),
+                egui::Button::new("Rescan folders"),
+            );
+            if rescan_btn.clicked() {
+                let roots: Vec<_> = self.dropped_folders.keys().cloned().collect();
+                for root in roots {
+                    self.scan_folder(root);
+                }
+            }
+        });
+    }
+
+    fn draw_pool_options(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Worker options").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Threads");
+                let max_threads = WorkerPoolOptions::available_threads();
+                ui.add(
+                    egui::DragValue::new(&mut self.pool_options.threads)
+                        .clamp_range(0..=max_threads)
+                        .custom_formatter(|n, _| {
+                            if n == 0.0 {
+                                String::from("auto")
+                            } else {
+                                format!("{n}")
+                            }
+                        }),
+                )
+                .on_hover_text(format!("0 uses all {max_threads} cores"));
+            });
+            ui.checkbox(
+                &mut self.pool_options.background_priority,
+                "Background priority",
+            )
+            .on_hover_text("Run workers at a lower OS priority (Linux only)");
+            ui.horizontal(|ui| {
+                let mut enabled = self.file_timeout.is_some();
+                if ui.checkbox(&mut enabled, "Per-file timeout").changed() {
+                    self.file_timeout = enabled.then_some(DEFAULT_FILE_TIMEOUT);
+                }
+                if let Some(timeout) = &mut self.file_timeout {
+                    let mut secs = timeout.as_secs_f64();
+                    let drag = egui::DragValue::new(&mut secs)
+                        .clamp_range(0.1..=86_400.0)
+                        .speed(0.5)
+                        .suffix(" s");
+                    if ui.add(drag).changed() {
+                        *timeout = Duration::from_secs_f64(secs);
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Files still running after this long are marked as timed out");
+            self.draw_retry_options(ui);
+        });
+    }
+
+    fn draw_output_options(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Output options").show(ui, |ui| {
+            let output = &mut self.output_policy;
+            egui::Grid::new("output options grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Write to");
+                    egui::ComboBox::from_id_source("output mode")
+                        .selected_text(output.mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in OutputMode::ALL {
+                                ui.selectable_value(&mut output.mode, mode, mode.label());
+                            }
+                        });
+                    ui.end_row();
+
+                    match output.mode {
+                        OutputMode::InPlace => return,
+                        OutputMode::Suffix => {
+                            ui.label("Suffix");
+                            ui.text_edit_singleline(&mut output.suffix)
+                                .on_hover_text("Added to the file name before the extension");
+                        }
+                        OutputMode::Mirror => {
+                            ui.label("Folder");
+                            ui.horizontal(|ui| {
+                                match &output.dir {
+                                    Some(dir) => ui.label(dir.display().to_string()),
+                                    None => ui.weak("None chosen"),
+                                };
+                                if ui.button("Choose…").clicked() {
+                                    if let Some(dir) = rfd::FileDialog::new()
+                                        .set_title("Output folder")
+                                        .pick_folder()
+                                    {
+                                        output.dir = Some(dir);
+                                    }
+                                }
+                            })
+                            .response
+                            .on_hover_text("Each dropped folder is recreated in here");
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label("If it exists");
+                    egui::ComboBox::from_id_source("output conflict")
+                        .selected_text(output.on_conflict.label())
+                        .show_ui(ui, |ui| {
+                            for strategy in ConflictStrategy::ALL {
+                                ui.selectable_value(
+                                    &mut output.on_conflict,
+                                    strategy,
+                                    strategy.label(),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                });
+            self.draw_backup_options(ui);
+        });
+    }
+
+    fn draw_backup_options(&mut self, ui: &mut egui::Ui) {
+        let backup = &mut self.backup_options;
+        let defaults = BackupOptions::default();
+        ui.checkbox(&mut backup.enabled, "Back up files before changing them")
+            .on_hover_text("Keeps the originals so \"Undo last job\" can restore them");
+        if !backup.enabled {
+            return;
+        }
+        ui.horizontal(|ui| {
+            let mut limited = backup.max_age.is_some();
+            if ui.checkbox(&mut limited, "Delete backups after").changed() {
+                backup.max_age = if limited { defaults.max_age } else { None };
+            }
+            if let Some(age) = &mut backup.max_age {
+                let mut days = age.as_secs_f64() / SECS_PER_DAY;
+                let drag = egui::DragValue::new(&mut days)
+                    .clamp_range(0.1..=365.0)
+                    .speed(0.1)
+                    .suffix(" days");
+                if ui.add(drag).changed() {
+                    *age = Duration::from_secs_f64(days * SECS_PER_DAY);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            let mut limited = backup.max_size.is_some();
+            if ui.checkbox(&mut limited, "Limit backups to").changed() {
+                backup.max_size = if limited { defaults.max_size } else { None };
+            }
+            if let Some(size) = &mut backup.max_size {
+                let mut mib = *size / MIB;
+                let drag = egui::DragValue::new(&mut mib)
+                    .clamp_range(1..=1 << 20)
+                    .suffix(" MiB");
+                if ui.add(drag).changed() {
+                    *size = mib * MIB;
+                }
+            }
+        })
+        .response
+        .on_hover_text("The oldest backups are deleted first");
+    }
+
+    fn draw_watch(&mut self, ui: &mut egui::Ui) {
+        let header = if self.watch_view.is_watching() {
+            "Watch folders (watching)"
+        } else {
+            "Watch folders"
+        };
+        egui::CollapsingHeader::new(header)
+            .id_source("watch folders")
+            .show(ui, |ui| {
+                let can_start = !self.dropped_folders.is_empty();
+                match self.watch_view.show(ui, can_start) {
+                    Some(WatchAction::Start) => self.start_watching(),
+                    Some(WatchAction::Stop) => self.watch_view.stop(),
+                    None => {}
+                }
+            });
+    }
+
+    /// Watches the dropped folders with the configured pipeline.
+    fn start_watching(&mut self) {
+        let roots: Vec<_> = self.dropped_folders.keys().cloned().collect();
+        match FolderWatcher::new(
+            roots,
+            &self.scan_options,
+            self.watch_view.options,
+            Some(self.repaint_ctx.clone()),
+        ) {
+            Ok(watcher) => match self.configured_pipeline() {
+                Ok(pipeline) => self.watch_view.start(watcher, pipeline),
+                Err(e) => self.watch_view.log_message(format!("Error: {e:#}")),
+            },
+            Err(e) => self.watch_view.log_message(format!("Error: {e:#}")),
+        }
+    }
+
+    fn update_watch(&mut self, ctx: &egui::Context) {
+        let mut watch_view = std::mem::replace(&mut self.watch_view, WatchView::new());
+        watch_view.update(ctx, |pipeline| self.new_engine(pipeline));
+        self.watch_view = watch_view;
+    }
+
+    fn draw_retry_options(&mut self, ui: &mut egui::Ui) {
+        let retry = &mut self.retry_policy;
+        let mut enabled = retry.is_enabled();
+        ui.horizontal(|ui| {
+            if ui
+                .checkbox(&mut enabled, "Retry transient failures")
+                .on_hover_text(
+                    "Try files that fail with an I/O error or time out reading again. \
+                     Files over the per-file timeout are not retried",
+                )
+                .changed()
+            {
+                retry.max_attempts = if enabled { DEFAULT_RETRY_ATTEMPTS } else { 1 };
+            }
+            if !enabled {
+                return;
+            }
+            ui.add(
+                egui::DragValue::new(&mut retry.max_attempts)
+                    .clamp_range(2..=20)
+                    .suffix(" attempts"),
+            );
+            let mut secs = retry.initial_backoff.as_secs_f64();
+            let backoff = egui::DragValue::new(&mut secs)
+                .clamp_range(0.0..=600.0)
+                .speed(0.1)
+                .prefix("backoff ")
+                .suffix(" s");
+            if ui
+                .add(backoff)
+                .on_hover_text("Wait before the first retry, doubled for every further one")
+                .changed()
+            {
+                retry.initial_backoff = Duration::from_secs_f64(secs);
+            }
+        });
+    }
+
+    fn draw_file_entry(
+        ui: &mut egui::Ui,
+        file: &PathBuf,
+        label: String,
+        file_status: &HashMap<PathBuf, FileStatus>,
+    ) -> bool {
+        let mut retain = true;
+        ui.horizontal(|ui| {
+            if ui.button("❌").clicked() {
+                retain = false;
+            }
+
+            if let Some(status) = file_status.get(file) {
+                ui.label(status.icon()).on_hover_text(status.hover_text());
+            }
+            ui.label(label);
+        });
+        retain
+    }
+
+    fn draw_files_list(&mut self, ui: &mut egui::Ui) {
+        ui.group(|ui| {
+            let mut files_to_retain = vec![true; self.dropped_files.len()];
+            ui.vertical(|ui| {
+                for (index, file) in self.dropped_files.iter().enumerate() {
+                    let display_label: String = file.display().to_string();
+                    files_to_retain[index] =
+                        Self::draw_file_entry(ui, file, display_label, &self.file_status);
+                }
+
+                let mut folders_to_remove = vec![];
+                for (root, folder) in self.dropped_folders.iter_mut() {
+                    let id = ui.make_persistent_id(root);
+                    egui::collapsing_header::CollapsingState::load_with_default_open(
+                        ui.ctx(),
+                        id,
+                        false,
+                    )
+                    .show_header(ui, |ui| {
+                        if ui.button("❌").clicked() {
+                            folders_to_remove.push(root.clone());
+                        }
+                        ui.label("📁");
+                        ui.label(root.display().to_string());
+                        if folder.scanning {
+                            ui.spinner();
+                        } else if let Some(error) = &folder.error {
+                            ui.label("⚠").on_hover_text(error);
+                        } else {
+                            ui.weak(format!("{} files", folder.files.len()));
+                        }
+                    })
+                    .body(|ui| {
+                        let file_status = &self.file_status;
+                        folder.files.retain(|file| {
+                            let label = file.strip_prefix(root).unwrap_or(file).display();
+                            Self::draw_file_entry(ui, file, label.to_string(), file_status)
+                        });
+                    });
+                }
+                for root in folders_to_remove {
+                    self.dropped_folders.remove(&root);
+                }
+            });
+
+            // Retain files based on removal button clicks:
+            let mut iter = files_to_retain.iter();
+            self.dropped_files.retain(|_| *iter.next().unwrap());
+        });
+    }
+
+    /// One row per step with its processor, settings and buttons to move or
+    /// remove it, followed by a button adding another step.
+    fn draw_pipeline_editor(&mut self, ui: &mut egui::Ui) {
+        let mut action = None;
+        let count = self.pipeline.len();
+        for (index, step) in self.pipeline.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    if count > 1 {
+                        ui.label(format!("{}.", index + 1));
+                    }
+                    Self::draw_processor_selector(ui, &self.processors, step);
+                    if count == 1 {
+                        return;
+                    }
+                    if ui
+                        .add_enabled(index > 0, egui::Button::new("⏶"))
+                        .on_hover_text("Run this step earlier")
+                        .clicked()
+                    {
+                        action = Some(StepAction::MoveUp(index));
+                    }
+                    if ui
+                        .add_enabled(index + 1 < count, egui::Button::new("⏷"))
+                        .on_hover_text("Run this step later")
+                        .clicked()
+                    {
+                        action = Some(StepAction::MoveDown(index));
+                    }
+                    if ui.button("❌").on_hover_text("Remove this step").clicked() {
+                        action = Some(StepAction::Remove(index));
+                    }
+                });
+                Self::draw_step_params(ui, &self.processors, step);
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui
+                .button("➕ Add step")
+                .on_hover_text("Run another processor on what the previous step produced")
+                .clicked()
+            {
+                self.pipeline.push(StepSpec::new(self.processors[0].name()));
+            }
+            if ui
+                .button("Load job…")
+                .on_hover_text("Take the pipeline, filters and worker options from a TOML file")
+                .clicked()
+            {
+                self.pick_job();
+            }
+            if ui
+                .button("Save job as…")
+                .on_hover_text("Save the pipeline, filters and worker options to share them")
+                .clicked()
+            {
+                self.save_job();
+            }
+        });
+
+        match action {
+            Some(StepAction::MoveUp(index)) => self.pipeline.swap(index - 1, index),
+            Some(StepAction::MoveDown(index)) => self.pipeline.swap(index, index + 1),
+            Some(StepAction::Remove(index)) => {
+                self.pipeline.remove(index);
+            }
+            None => {}
+        }
+    }
+
+    fn draw_processor_selector(
+        ui: &mut egui::Ui,
+        processors: &[Arc<dyn FileProcessor>],
+        step: &mut StepSpec,
+    ) {
+        let description = processors
+            .iter()
+            .find(|p| p.name() == step.processor)
+            .map_or("", |p| p.description());
+        egui::ComboBox::from_id_source("processor selector")
+            .selected_text(&step.processor)
+            .show_ui(ui, |ui| {
+                for processor in processors {
+                    let selected = processor.name() == step.processor;
+                    if ui
+                        .selectable_label(selected, processor.name())
+                        .on_hover_text(processor.description())
+                        .clicked()
+                        && !selected
+                    {
+                        // Settings of one processor mean nothing to another
+                        *step = StepSpec::new(processor.name());
+                    }
+                }
+            })
+            .response
+            .on_hover_text(description);
+    }
+
+    fn draw_step_params(
+        ui: &mut egui::Ui,
+        processors: &[Arc<dyn FileProcessor>],
+        step: &mut StepSpec,
+    ) {
+        let Some(processor) = processors.iter().find(|p| p.name() == step.processor) else {
+            return;
+        };
+        let specs = processor.params();
+        if specs.is_empty() {
+            return;
+        }
+        egui::Grid::new("processor params")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for spec in specs {
+                    ui.label(spec.key).on_hover_text(spec.description);
+                    let value = step.params.entry(spec.key.to_owned()).or_default();
+                    ui.add(egui::TextEdit::singleline(value).hint_text(spec.default))
+                        .on_hover_text(spec.description);
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// The pipeline steps with the settings entered for them, empty ones left out.
+    fn pipeline_steps(&self) -> Vec<StepSpec> {
+        self.pipeline
+            .iter()
+            .map(|step| StepSpec {
+                processor: step.processor.clone(),
+                params: step
+                    .params
+                    .iter()
+                    .filter(|(_, value)| !value.trim().is_empty())
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The configured pipeline, empty settings left at their default.
+    fn configured_pipeline(&self) -> anyhow::Result<Pipeline> {
+        Pipeline::from_specs(&self.pipeline_steps())
+    }
+
+    fn update_progress(&mut self) {
+        for event in self.file_processing_thread.poll_progress() {
+            let (path, status) = match event {
+                ProgressEvent::Started(path) => (path, FileStatus::Running { done: 0, total: 0 }),
+                ProgressEvent::Planned(files) => {
+                    self.file_status = files
+                        .iter()
+                        .map(|f| (f.clone(), FileStatus::Pending))
+                        .collect();
+                    self.job_progress = Some(JobProgress::new(files.len()));
+                    continue;
+                }
+                ProgressEvent::Bytes(path, done, total) => {
+                    self.file_status
+                        .insert(path, FileStatus::Running { done, total });
+                    continue;
+                }
+                ProgressEvent::Finished(path, duration) => (path, FileStatus::Succeeded(duration)),
+                ProgressEvent::Failed(path, duration) => (path, FileStatus::Failed(duration)),
+                ProgressEvent::Cancelled(path) => (path, FileStatus::Cancelled),
+                ProgressEvent::TimedOut(path, duration) => {
+                    self.overdue_files.insert(path.clone());
+                    (path, FileStatus::TimedOut(duration))
+                }
+                ProgressEvent::ReturnedLate(path) => {
+                    self.overdue_files.remove(&path);
+                    continue;
+                }
+            };
+            if !matches!(status, FileStatus::Running { .. }) {
+                if let Some(progress) = &mut self.job_progress {
+                    progress.finished += 1;
+                }
+            }
+            self.file_status.insert(path, status);
+        }
+    }
+
+    fn draw_progress_bar(&self, ui: &mut egui::Ui) {
+        let Some(progress) = &self.job_progress else {
+            return;
+        };
+        let eta = match progress.eta() {
+            Some(eta) => {
+                let eta = Duration::from_secs(eta.as_secs());
+                format!("ETA {}", humantime::format_duration(eta))
+            }
+            None => String::from("ETA unknown"),
+        };
+        ui.add(egui::ProgressBar::new(progress.fraction()).text(format!(
+            "{}/{} — {}",
+            progress.finished, progress.total, eta
+        )));
+    }
+
+    fn draw_overdue_files(&self, ui: &mut egui::Ui) {
+        if self.overdue_files.is_empty() {
+            return;
+        }
+        let header = format!(
+            "{} file(s) still running past their deadline",
+            self.overdue_files.len()
+        );
+        egui::CollapsingHeader::new(egui::RichText::new(header).color(ui.visuals().warn_fg_color))
+            .id_source("overdue files")
+            .show(ui, |ui| {
+                for path in &self.overdue_files {
+                    ui.label(path.display().to_string());
+                }
+            });
+    }
+
+    fn results_summary(&self) -> String {
+        let counts: Vec<_> = ResultStatus::ALL
+            .iter()
+            .map(|&status| format!("{} {}", self.results_view.count(status), status.label()))
+            .collect();
+        counts.join(", ")
+    }
+
+    fn gather_processing_results(&mut self) {
+        // gather results, cleanup thread
+        let mut results = self.file_processing_thread.get_results();
+        if let Some(mut base) = self.retry_base.take() {
+            // A retry only ran the failed files, put their new results in place of the old ones
+            for result in results {
+                match base.iter_mut().find(|r| r.path == result.path) {
+                    Some(slot) => *slot = result,
+                    None => base.push(result),
+                }
+            }
+            results = base;
+        }
+        if self.file_processing_thread.is_dry_run() {
+            self.preview_view.set_results(&results);
+        }
+        self.results_view.set_results(results);
+        self.result_msg = self.results_summary();
+        if self.file_processing_thread.is_dry_run() {
+            self.result_msg = format!("Dry run, nothing was written: {}", self.result_msg);
+        }
+        match self.file_processing_thread.get_job_output() {
+            Ok(output) => {
+                for file in &output.produced_files {
+                    self.result_msg += &format!("\nWrote {}", file.display());
+                }
+                for (key, value) in &output.fields {
+                    self.result_msg += &format!("\n{key}: {value}");
+                }
+            }
+            Err(error) => self.result_msg += &format!("\nError: {}", error.message()),
+        }
+
+        let finished = std::mem::replace(
+            &mut self.file_processing_thread,
+            FileProcessingThread::new(self.processors[0].clone()),
+        );
+        // Applying the preview and retrying run with the pipeline and options of this job
+        let last_engine = finished.rerun();
+        if last_engine.is_dry_run() && !self.preview_view.is_empty() {
+            self.preview_engine = Some(last_engine.rerun().with_dry_run(false));
+        }
+        self.last_engine = Some(last_engine);
+        self.processing_btn_enabled = true;
+        self.refresh_last_job();
+        // The engine stops tracking stuck processors once the job is over
+        self.overdue_files.clear();
+    }
+
+    fn failed_files(&self) -> Vec<PathBuf> {
+        self.results_view
+            .results()
+            .iter()
+            .filter(|r| r.is_failure())
+            .map(|r| r.path.clone())
+            .collect()
+    }
+
+    fn start_processing_files(&mut self, dry_run: bool) {
+        self.retry_base = None;
+        self.start_job(self.files_to_process(), dry_run);
+    }
+
+    /// An engine for `pipeline` using the worker options from the UI.
+    fn new_engine(&self, pipeline: Pipeline) -> FileProcessingThread {
+        FileProcessingThread::new(pipeline)
+            .with_repaint_context(self.repaint_ctx.clone())
+            .with_pool_options(self.pool_options)
+            .with_timeout(self.file_timeout)
+            .with_retry_policy(self.retry_policy)
+            .with_output_policy(
+                self.output_policy.clone(),
+                self.dropped_folders.keys().cloned().collect(),
+            )
+            .with_backups(BackupStore::in_data_dir(self.backup_options))
+    }
+
+    fn refresh_last_job(&mut self) {
+        self.last_job = BackupStore::in_data_dir(self.backup_options)
+            .and_then(|store| store.last_job().ok().flatten());
+    }
+
+    /// Restores the files the last job changed and lists what happened to each.
+    fn undo_last_job(&mut self) {
+        // Watched folders may have run jobs since we last looked
+        self.refresh_last_job();
+        let Some(job) = self.last_job.take() else {
+            return;
+        };
+        self.result_msg = match job.undo() {
+            Ok(report) => {
+                let mut lines = vec![format!("Undid {}: {}", job.name, report.summary())];
+                lines.extend(
+                    report
+                        .restored
+                        .iter()
+                        .map(|path| format!("Restored {}", path.display())),
+                );
+                lines.extend(
+                    report
+                        .removed
+                        .iter()
+                        .map(|path| format!("Removed {}", path.display())),
+                );
+                lines.extend(
+                    report
+                        .skipped
+                        .iter()
+                        .map(|(path, reason)| format!("Left {} alone: {reason}", path.display())),
+                );
+                lines.join("\n")
+            }
+            Err(e) => format!("Failed to undo {}: {e:#}", job.name),
+        };
+        // The results describe files that were just put back
+        self.results_view.clear();
+        self.clear_preview();
+        self.refresh_last_job();
+    }
+
+    /// Runs the configured pipeline on `files_as_list`, only previewing the
+    /// changes if `dry_run` is set.
+    fn start_job(&mut self, files_as_list: Vec<PathBuf>, dry_run: bool) {
+        let pipeline = match self.configured_pipeline() {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                self.result_msg = format!("Error: {e:#}");
+                return;
+            }
+        };
+        let engine = self.new_engine(pipeline).with_dry_run(dry_run);
+        self.run_engine(engine, files_as_list);
+    }
+
+    /// Applies the accepted changes of the preview with the settings of its
+    /// dry run, leaving out files that changed since.
+    fn apply_preview(&mut self, files: Vec<PathBuf>) {
+        let Some(engine) = self.preview_engine.take() else {
+            return;
+        };
+        let (files, changed): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|file| self.preview_view.is_unchanged(file));
+        self.notice = (!changed.is_empty()).then(|| {
+            let names: Vec<_> = changed.iter().map(|f| f.display().to_string()).collect();
+            format!(
+                "Left alone as they changed since the preview: {}",
+                names.join(", ")
+            )
+        });
+        self.retry_base = None;
+        if files.is_empty() {
+            self.clear_preview();
+            return;
+        }
+        self.run_engine(engine, files);
+    }
+
+    /// Runs the last job again on its `failed` files, with the pipeline and
+    /// options it ran with. All of its inputs are prepared again, as e.g. a
+    /// verify job needs its manifests.
+    fn retry_failed(&mut self, failed: Vec<PathBuf>) {
+        let Some(last_engine) = &self.last_engine else {
+            return;
+        };
+        let engine = last_engine.rerun().with_only_files(failed);
+        let inputs = engine.file_list().to_vec();
+        self.retry_base = Some(self.results_view.results().to_vec());
+        self.run_engine(engine, inputs);
+    }
+
+    fn run_engine(&mut self, engine: FileProcessingThread, files_as_list: Vec<PathBuf>) {
+        self.file_status = files_as_list
+            .iter()
+            .map(|f| (f.clone(), FileStatus::Pending))
+            .collect();
+        self.job_progress = Some(JobProgress::new(files_as_list.len()));
+        self.file_processing_thread = engine;
+        self.file_processing_thread.set_file_list(files_as_list);
+        self.file_processing_thread.run();
+
+        self.processing_btn_enabled = false;
+        self.result_msg = String::new();
+        self.results_view.clear();
+        self.clear_preview();
+    }
+
+    fn clear_preview(&mut self) {
+        self.preview_view.clear();
+        self.preview_engine = None;
+    }
+}
@@ -0,0 +1,481 @@
+use crate::error::ProcessingError;
+use crate::output::{self, absolute};
+use anyhow::{Context as _, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use walkdir::WalkDir;
+
+const JOB_FILE: &str = "job.json";
+const JOURNAL_FILE: &str = "journal.jsonl";
+/// Marks a job whose changes were undone.
+const UNDONE_FILE: &str = "undone";
+const FILES_DIR: &str = "files";
+
+/// Whether jobs back up the files they write, and how long backups are kept.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupOptions {
+    pub enabled: bool,
+    /// Backups of jobs started longer ago are deleted when the next job starts.
+    pub max_age: Option<Duration>,
+    /// The oldest backups are deleted until all of them together fit in this many bytes.
+    pub max_size: Option<u64>,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        BackupOptions {
+            enabled: true,
+            max_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            max_size: Some(1 << 30),
+        }
+    }
+}
+
+/// Where the backups of every job are kept, one folder per job.
+#[derive(Clone)]
+pub struct BackupStore {
+    root: PathBuf,
+    options: BackupOptions,
+}
+
+impl BackupStore {
+    pub fn new(root: PathBuf, options: BackupOptions) -> Self {
+        BackupStore { root, options }
+    }
+
+    /// The store in the application's data folder, shared by the window and
+    /// the command line.
+    pub fn in_data_dir(options: BackupOptions) -> Option<Self> {
+        let dir = eframe::storage_dir(crate::APP_NAME)?;
+        Some(Self::new(dir.join("backups"), options))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.options.enabled
+    }
+
+    /// Prunes old backups and opens the backup area of a new job.
+    pub fn start_job(&self, name: &str) -> Result<JobBackup> {
+        // Failing to prune must not keep the job from running
+        let _ = self.prune();
+
+        static JOBS: AtomicU64 = AtomicU64::new(0);
+        let started = SystemTime::now();
+        let millis = started.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let id = format!(
+            "{:013}-{}-{}",
+            millis.as_millis(),
+            process::id(),
+            JOBS.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = self.root.join(id);
+        fs::create_dir_all(dir.join(FILES_DIR))
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Creating the backup folder {:?}", dir))?;
+        let info = JobInfo {
+            name: name.to_owned(),
+            started,
+        };
+        let journal = serde_json::to_vec_pretty(&info)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| output::write_atomic(&dir.join(JOB_FILE), &json))
+            .and_then(|()| {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(dir.join(JOURNAL_FILE))
+                    .map_err(|e| ProcessingError::from(e).into())
+            })
+            .with_context(|| format!("Starting the backup journal in {:?}", dir))?;
+        Ok(JobBackup {
+            dir,
+            backups: AtomicU64::new(0),
+            state: Mutex::new(JournalState {
+                journal,
+                originals: HashMap::new(),
+                entries: 0,
+            }),
+        })
+    }
+
+    /// The most recent job that wrote files and wasn't undone yet.
+    pub fn last_job(&self) -> Result<Option<JobRecord>> {
+        for dir in self.job_dirs()?.into_iter().rev() {
+            if dir.join(UNDONE_FILE).exists() {
+                continue;
+            }
+            let record = JobRecord::load(&dir)?;
+            if !record.entries.is_empty() {
+                return Ok(Some(record));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Deletes the backups of jobs older than `max_age`, then the oldest ones
+    /// until the rest fit in `max_size`.
+    pub fn prune(&self) -> Result<()> {
+        let now = SystemTime::now();
+        let mut kept = vec![];
+        for dir in self.job_dirs()? {
+            let started = fs::read(dir.join(JOB_FILE))
+                .ok()
+                .and_then(|json| serde_json::from_slice::<JobInfo>(&json).ok())
+                .map(|info| info.started);
+            let age = started.and_then(|started| now.duration_since(started).ok());
+            match (age, self.options.max_age) {
+                (Some(age), Some(max_age)) if age > max_age => remove_job_dir(&dir)?,
+                _ => kept.push(dir),
+            }
+        }
+
+        let Some(max_size) = self.options.max_size else {
+            return Ok(());
+        };
+        let sizes: Vec<_> = kept.iter().map(|dir| dir_size(dir)).collect();
+        let mut total: u64 = sizes.iter().sum();
+        for (dir, size) in kept.iter().zip(sizes) {
+            if total <= max_size {
+                break;
+            }
+            remove_job_dir(dir)?;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    /// Folders of all jobs, oldest first.
+    fn job_dirs(&self) -> Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => {
+                return Err(ProcessingError::from(e))
+                    .with_context(|| format!("Reading the backups in {:?}", self.root))
+            }
+        };
+        let mut dirs: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.join(JOB_FILE).is_file())
+            .collect();
+        // Folder names start with the zero padded start time
+        dirs.sort();
+        Ok(dirs)
+    }
+}
+
+fn remove_job_dir(dir: &Path) -> Result<()> {
+    fs::remove_dir_all(dir)
+        .map_err(ProcessingError::from)
+        .with_context(|| format!("Removing old backups {:?}", dir))
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn digest(content: &[u8]) -> String {
+    blake3::hash(content).to_hex().to_string()
+}
+
+#[derive(Serialize, Deserialize)]
+struct JobInfo {
+    name: String,
+    started: SystemTime,
+}
+
+/// A write the job made, appended to the journal once it succeeded.
+#[derive(Clone, Serialize, Deserialize)]
+struct JournalEntry {
+    path: PathBuf,
+    /// The copy of the file from before the job, relative to the job's
+    /// folder, or `None` if the job created the file.
+    backup: Option<PathBuf>,
+    /// BLAKE3 digest of the content written.
+    written: String,
+}
+
+struct JournalState {
+    journal: fs::File,
+    /// The backup of every file written so far, see `JournalEntry::backup`.
+    originals: HashMap<PathBuf, Option<PathBuf>>,
+    entries: usize,
+}
+
+/// The backup area of one running job. A job that wrote nothing leaves no
+/// backup behind.
+pub struct JobBackup {
+    dir: PathBuf,
+    backups: AtomicU64,
+    state: Mutex<JournalState>,
+}
+
+impl JobBackup {
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes `content` to `path` like `output::write_atomic`, keeping a copy
+    /// of the file first if the job didn't write to it before, and journals the change.
+    pub fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        // Journal the file a symlink points to, which is what gets replaced
+        let path = fs::canonicalize(path).unwrap_or_else(|_| absolute(path));
+        let known = self
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .originals
+            .get(&path)
+            .cloned();
+        let backup = match known {
+            Some(backup) => backup,
+            None => {
+                let backup = self.snapshot(&path)?;
+                let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+                state
+                    .originals
+                    .entry(path.clone())
+                    .or_insert(backup)
+                    .clone()
+            }
+        };
+
+        output::write_atomic(&path, content)?;
+
+        let entry = JournalEntry {
+            path,
+            backup,
+            written: digest(content),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.entries += 1;
+        state
+            .journal
+            .write_all(line.as_bytes())
+            .and_then(|()| state.journal.flush())
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Writing the backup journal in {:?}", self.dir))
+    }
+
+    /// Copies `path` into the backup area, if it exists.
+    fn snapshot(&self, path: &Path) -> Result<Option<PathBuf>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let name = format!(
+            "{}-{}",
+            self.backups.fetch_add(1, Ordering::Relaxed),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+        let backup = Path::new(FILES_DIR).join(name);
+        fs::copy(path, self.dir.join(&backup))
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Backing up {:?}", path))?;
+        Ok(Some(backup))
+    }
+}
+
+impl Drop for JobBackup {
+    fn drop(&mut self) {
+        let entries = self.state.get_mut().map_or(1, |state| state.entries);
+        if entries == 0 {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// A finished job as its backup folder describes it.
+pub struct JobRecord {
+    dir: PathBuf,
+    /// The pipeline that ran.
+    pub name: String,
+    pub started: SystemTime,
+    entries: Vec<JournalEntry>,
+}
+
+/// What undoing a job did to each file it had written.
+#[derive(Default)]
+pub struct UndoReport {
+    /// Files put back the way they were before the job.
+    pub restored: Vec<PathBuf>,
+    /// Files the job had created.
+    pub removed: Vec<PathBuf>,
+    /// Files left alone, with the reason.
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+impl UndoReport {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} restored, {} removed, {} left alone",
+            self.restored.len(),
+            self.removed.len(),
+            self.skipped.len()
+        )
+    }
+}
+
+impl JobRecord {
+    fn load(dir: &Path) -> Result<Self> {
+        let read = || -> Result<Self> {
+            let info: JobInfo = serde_json::from_slice(&fs::read(dir.join(JOB_FILE))?)?;
+            let journal = BufReader::new(fs::File::open(dir.join(JOURNAL_FILE))?);
+            let mut entries = vec![];
+            for line in journal.lines() {
+                let line = line?;
+                // A line cut short by a crash mid-write is all that can be wrong here
+                match serde_json::from_str(&line) {
+                    Ok(entry) => entries.push(entry),
+                    Err(_) => break,
+                }
+            }
+            Ok(JobRecord {
+                dir: dir.to_path_buf(),
+                name: info.name,
+                started: info.started,
+                entries,
+            })
+        };
+        read().with_context(|| format!("Reading the backup of {:?}", dir))
+    }
+
+    /// The files the job wrote, each listed once.
+    pub fn files(&self) -> Vec<&Path> {
+        let mut files: Vec<&Path> = vec![];
+        for entry in &self.entries {
+            if !files.contains(&entry.path.as_path()) {
+                files.push(&entry.path);
+            }
+        }
+        files
+    }
+
+    /// Puts every file the job wrote back the way it was, leaving alone
+    /// the ones that changed since.
+    pub fn undo(&self) -> Result<UndoReport> {
+        // The first entry of a file has its backup, the last one what it should contain now
+        let mut files: IndexMap<&Path, (&JournalEntry, &JournalEntry)> = IndexMap::new();
+        for entry in &self.entries {
+            files
+                .entry(&entry.path)
+                .and_modify(|(_, last)| *last = entry)
+                .or_insert((entry, entry));
+        }
+
+        let mut report = UndoReport::default();
+        for (path, (first, last)) in files {
+            let path = path.to_path_buf();
+            let current = match fs::read(&path) {
+                Ok(current) => Some(current),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    report.skipped.push((path, format!("can't be read: {e}")));
+                    continue;
+                }
+            };
+            if current
+                .as_ref()
+                .is_some_and(|current| digest(current) != last.written)
+            {
+                report
+                    .skipped
+                    .push((path, String::from("changed since the job")));
+                continue;
+            }
+            match (&first.backup, current) {
+                (Some(backup), _) => {
+                    let restored = fs::read(self.dir.join(backup))
+                        .map_err(ProcessingError::from)
+                        .with_context(|| format!("Reading the backup {:?}", backup))
+                        .and_then(|original| output::write_atomic(&path, &original));
+                    match restored {
+                        Ok(()) => report.restored.push(path),
+                        Err(e) => report.skipped.push((path, format!("{e:#}"))),
+                    }
+                }
+                (None, Some(_)) => match fs::remove_file(&path) {
+                    Ok(()) => report.removed.push(path),
+                    Err(e) => report
+                        .skipped
+                        .push((path, format!("can't be removed: {e}"))),
+                },
+                // Created by the job and already gone again
+                (None, None) => {}
+            }
+        }
+
+        fs::write(self.dir.join(UNDONE_FILE), b"")
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Marking {:?} as undone", self.dir))?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_leaves_files_changed_since_the_job_alone() {
+        let dir = std::env::temp_dir().join(format!("backup-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let data = dir.join("data");
+        fs::create_dir_all(&data).unwrap();
+        let kept = data.join("kept.txt");
+        let changed = data.join("changed.txt");
+        let created = data.join("created.txt");
+        fs::write(&kept, "kept before").unwrap();
+        fs::write(&changed, "changed before").unwrap();
+
+        let store = BackupStore::new(dir.join("backups"), BackupOptions::default());
+        let backup = store.start_job("test").unwrap();
+        backup.write(&kept, b"kept by the job").unwrap();
+        backup.write(&kept, b"kept by the job again").unwrap();
+        backup.write(&changed, b"changed by the job").unwrap();
+        backup.write(&created, b"created by the job").unwrap();
+        drop(backup);
+        fs::write(&changed, "changed by someone else").unwrap();
+
+        let job = store.last_job().unwrap().unwrap();
+        assert_eq!(job.name, "test");
+        let report = job.undo().unwrap();
+        let canonical = |path: &Path| fs::canonicalize(path).unwrap();
+        assert_eq!(report.restored, [canonical(&kept)]);
+        assert_eq!(report.removed, [canonical(&data).join("created.txt")]);
+        assert_eq!(
+            report.skipped,
+            [(canonical(&changed), String::from("changed since the job"))]
+        );
+        assert_eq!(fs::read_to_string(&kept).unwrap(), "kept before");
+        assert_eq!(
+            fs::read_to_string(&changed).unwrap(),
+            "changed by someone else"
+        );
+        assert!(!created.exists());
+        assert!(store.last_job().unwrap().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
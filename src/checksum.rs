@@ -0,0 +1,435 @@
+use crate::error::ProcessingError;
+use crate::output::{absolute, OutputTarget};
+use crate::processor::{
+    FileProcessor, ParamSpec, Params, PreparedJob, ProcessContext, ProcessOutput,
+};
+use crate::results::{FileResult, ResultStatus};
+use crate::verify;
+use anyhow::{Context as _, Result};
+use rayon::prelude::*;
+use sha2::Digest;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::{self, Path, PathBuf},
+    sync::Arc,
+};
+
+/// Files are read and hashed this many bytes at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+
+const PARAMS: &[ParamSpec] = &[
+    ParamSpec {
+        key: "algorithms",
+        description: "Digests to compute, any of sha256, sha1, md5, blake3 and crc32",
+        default: "sha256",
+    },
+    ParamSpec {
+        key: "manifest",
+        description: "Empty for no manifest, `adjacent` to write one per folder next to \
+                      the inputs, or the path of a single manifest file",
+        default: "",
+    },
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Blake3,
+    Crc32,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 5] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha1,
+        HashAlgorithm::Md5,
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Crc32,
+    ];
+
+    /// Also used as the output field holding the digest.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Crc32 => "crc32",
+        }
+    }
+
+    /// Conventional name of a manifest in the format of `sha256sum` and
+    /// friends. CRC32 has no such format.
+    pub fn manifest_name(&self) -> Option<&'static str> {
+        match self {
+            HashAlgorithm::Sha256 => Some("SHA256SUMS"),
+            HashAlgorithm::Sha1 => Some("SHA1SUMS"),
+            HashAlgorithm::Md5 => Some("MD5SUMS"),
+            HashAlgorithm::Blake3 => Some("B3SUMS"),
+            HashAlgorithm::Crc32 => None,
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self> {
+        let normalized = name.replace('-', "").to_ascii_lowercase();
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == normalized)
+            .with_context(|| format!("Unknown hash algorithm {name:?}"))
+    }
+
+    /// Parses a comma or whitespace separated list, dropping duplicates.
+    pub fn parse_list(names: &str) -> Result<Vec<Self>> {
+        let mut algorithms = vec![];
+        for name in names
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|name| !name.is_empty())
+        {
+            let algorithm = HashAlgorithm::parse(name)?;
+            if !algorithms.contains(&algorithm) {
+                algorithms.push(algorithm);
+            }
+        }
+        if algorithms.is_empty() {
+            anyhow::bail!("No hash algorithm selected");
+        }
+        Ok(algorithms)
+    }
+
+    fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+            HashAlgorithm::Sha1 => Hasher::Sha1(sha1::Sha1::new()),
+            HashAlgorithm::Md5 => Hasher::Md5(md5::Md5::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+        }
+    }
+}
+
+enum Hasher {
+    Sha256(sha2::Sha256),
+    Sha1(sha1::Sha1),
+    Md5(md5::Md5),
+    Blake3(Box<blake3::Hasher>),
+    Crc32(crc32fast::Hasher),
+}
+
+impl Hasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            Hasher::Crc32(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize_hex(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Sha1(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Md5(hasher) => hex::encode(hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Crc32(hasher) => format!("{:08x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Reads `file`, or the pipeline input in `ctx`, in chunks, feeding each chunk to all `algorithms` in parallel,
+/// and returns the hex digests in the same order.
+pub fn hash_file(
+    file: &Path,
+    algorithms: &[HashAlgorithm],
+    ctx: &ProcessContext,
+) -> Result<Vec<String>> {
+    let total = match ctx.input() {
+        Some(input) => input.len() as u64,
+        None => fs::metadata(file).map_or(0, |m| m.len()),
+    };
+    let mut input = ctx.open_input(file)?;
+    let mut hashers: Vec<_> = algorithms.iter().map(HashAlgorithm::hasher).collect();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut done = 0u64;
+    loop {
+        ctx.check_cancelled()?;
+        let read = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(ProcessingError::from(e))
+                    .with_context(|| format!("Reading {:?} at byte {done}", file));
+            }
+        };
+        let chunk = &buffer[..read];
+        if hashers.len() > 1 {
+            hashers
+                .par_iter_mut()
+                .for_each(|hasher| hasher.update(chunk));
+        } else {
+            hashers.iter_mut().for_each(|hasher| hasher.update(chunk));
+        }
+        done += read as u64;
+        ctx.report_progress(done, total);
+    }
+    Ok(hashers.into_iter().map(Hasher::finalize_hex).collect())
+}
+
+/// Where the checksum processor writes its manifests.
+#[derive(Clone, PartialEq)]
+pub enum ManifestTarget {
+    None,
+    /// One manifest per algorithm in every folder that had inputs, naming the files in it.
+    Adjacent,
+    /// A single manifest, naming files relative to its folder where possible.
+    Path(PathBuf),
+}
+
+impl ManifestTarget {
+    pub fn parse(value: &str) -> Self {
+        match value.trim() {
+            "" | "none" => ManifestTarget::None,
+            "adjacent" => ManifestTarget::Adjacent,
+            path => ManifestTarget::Path(PathBuf::from(path)),
+        }
+    }
+}
+
+/// Streams every file once through all selected hash algorithms.
+pub struct ChecksumProcessor {
+    algorithms: Vec<HashAlgorithm>,
+    manifest: ManifestTarget,
+}
+
+impl Default for ChecksumProcessor {
+    fn default() -> Self {
+        ChecksumProcessor {
+            algorithms: vec![HashAlgorithm::Sha256],
+            manifest: ManifestTarget::None,
+        }
+    }
+}
+
+impl ChecksumProcessor {
+    /// The algorithms that get a manifest: all that have a manifest format
+    /// for `Adjacent`, and only the first of them for a single `Path`.
+    fn manifest_algorithms(&self) -> Vec<HashAlgorithm> {
+        let mut algorithms: Vec<_> = self
+            .algorithms
+            .iter()
+            .copied()
+            .filter(|algorithm| algorithm.manifest_name().is_some())
+            .collect();
+        if let ManifestTarget::Path(_) = self.manifest {
+            algorithms.truncate(1);
+        }
+        algorithms
+    }
+
+    /// Whether `path` is one of the manifests this processor writes.
+    fn is_manifest(&self, path: &Path) -> bool {
+        match &self.manifest {
+            ManifestTarget::None => false,
+            ManifestTarget::Adjacent => self.manifest_algorithms().iter().any(|algorithm| {
+                path.file_name().and_then(|name| name.to_str()) == algorithm.manifest_name()
+            }),
+            ManifestTarget::Path(manifest) => absolute(manifest) == absolute(path),
+        }
+    }
+
+    /// Collects the manifest entries for every successfully hashed file, by manifest path.
+    fn manifest_entries(&self, results: &[FileResult]) -> BTreeMap<PathBuf, ManifestEntries> {
+        let mut manifests: BTreeMap<PathBuf, ManifestEntries> = BTreeMap::new();
+        for result in results
+            .iter()
+            .filter(|r| r.status == ResultStatus::Succeeded)
+        {
+            for algorithm in self.manifest_algorithms() {
+                let Some(digest) = result.output.fields.get(algorithm.name()) else {
+                    continue;
+                };
+                let (manifest, name) = match &self.manifest {
+                    ManifestTarget::None => continue,
+                    ManifestTarget::Adjacent => {
+                        let folder = result.path.parent().unwrap_or(Path::new(""));
+                        let name = result.path.file_name().unwrap_or_default();
+                        let manifest_name = algorithm.manifest_name().unwrap_or_default();
+                        (
+                            folder.join(manifest_name),
+                            name.to_string_lossy().into_owned(),
+                        )
+                    }
+                    ManifestTarget::Path(manifest) => {
+                        (manifest.clone(), relative_name(manifest, &result.path))
+                    }
+                };
+                manifests
+                    .entry(manifest)
+                    .or_insert_with(|| ManifestEntries {
+                        algorithm,
+                        digests: BTreeMap::new(),
+                    })
+                    .digests
+                    .insert(name, digest.clone());
+            }
+        }
+        manifests
+    }
+}
+
+/// The digests one manifest lists, by file name.
+struct ManifestEntries {
+    algorithm: HashAlgorithm,
+    digests: BTreeMap<String, String>,
+}
+
+impl ManifestEntries {
+    /// Adds the entries of the existing manifest at `path` for files this job
+    /// didn't hash, so a run over some of the files keeps the others listed.
+    fn merge_existing(&mut self, path: &Path) -> Result<()> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(ProcessingError::from(e))
+                    .with_context(|| format!("Reading manifest {:?}", path))
+            }
+        };
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = verify::parse_line(line, Some(self.algorithm)).and_then(
+                |(algorithm, digest, name)| {
+                    if algorithm != self.algorithm {
+                        return Err(format!("expected a {} digest", self.algorithm.name()));
+                    }
+                    Ok((digest, name))
+                },
+            );
+            let (digest, name) = parsed
+                .map_err(ProcessingError::InvalidFormat)
+                .with_context(|| {
+                    format!(
+                        "Line {} of {:?}, not adding to that manifest",
+                        index + 1,
+                        path
+                    )
+                })?;
+            self.digests
+                .entry(name)
+                .or_insert_with(|| digest.to_ascii_lowercase());
+        }
+        Ok(())
+    }
+}
+
+/// `file` relative to the folder of `manifest` if it is below it, else absolute.
+fn relative_name(manifest: &Path, file: &Path) -> String {
+    let manifest = absolute(manifest);
+    let file = absolute(file);
+    let folder = manifest.parent().unwrap_or(Path::new(""));
+    let name = file.strip_prefix(folder).unwrap_or(&file);
+    name.to_string_lossy().replace(path::MAIN_SEPARATOR, "/")
+}
+
+/// A line as `sha256sum` writes it, including its escaping of odd file names.
+fn manifest_line(digest: &str, name: &str) -> String {
+    if name.contains(['\\', '\n', '\r']) {
+        let name = name
+            .replace('\\', "\\\\")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        format!("\\{digest}  {name}\n")
+    } else {
+        format!("{digest}  {name}\n")
+    }
+}
+
+impl FileProcessor for ChecksumProcessor {
+    fn name(&self) -> &str {
+        "Checksum"
+    }
+
+    fn description(&self) -> &str {
+        "Computes SHA-256, SHA-1, MD5, BLAKE3 or CRC32 digests and can write sha256sum style manifests"
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let digests = hash_file(file, &self.algorithms, ctx)?;
+        let mut output = ProcessOutput::new();
+        for (algorithm, digest) in self.algorithms.iter().zip(digests) {
+            output = output.with_field(algorithm.name(), digest);
+        }
+        Ok(output)
+    }
+
+    fn params(&self) -> &'static [ParamSpec] {
+        PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Arc<dyn FileProcessor>> {
+        let mut processor = ChecksumProcessor::default();
+        if let Some(algorithms) = params.get("algorithms") {
+            processor.algorithms = HashAlgorithm::parse_list(algorithms)?;
+        }
+        if let Some(manifest) = params.get("manifest") {
+            processor.manifest = ManifestTarget::parse(manifest);
+        }
+        if processor.manifest != ManifestTarget::None && processor.manifest_algorithms().is_empty()
+        {
+            anyhow::bail!("CRC32 has no manifest format, also select another algorithm");
+        }
+        Ok(Arc::new(processor))
+    }
+
+    fn prepare(&self, files: &[PathBuf]) -> Result<PreparedJob> {
+        // The manifests of an earlier run would end up listing themselves
+        let files = files
+            .iter()
+            .filter(|file| !self.is_manifest(file))
+            .cloned()
+            .collect();
+        Ok(PreparedJob {
+            files,
+            processor: None,
+        })
+    }
+
+    fn finish(&self, results: &[FileResult], target: &OutputTarget) -> Result<ProcessOutput> {
+        let mut output = ProcessOutput::new();
+        for (manifest, mut entries) in self.manifest_entries(results) {
+            let Some(destination) = target.resolve_job_output(&manifest)? else {
+                let skipped = format!("{} already exists", manifest.display());
+                output
+                    .fields
+                    .entry(String::from("not written"))
+                    .and_modify(|value| *value = format!("{value}, {skipped}"))
+                    .or_insert(skipped);
+                continue;
+            };
+            if destination == manifest {
+                entries.merge_existing(&manifest)?;
+            }
+            let content: String = entries
+                .digests
+                .iter()
+                .map(|(name, digest)| manifest_line(digest, name))
+                .collect();
+            target
+                .write_job_output(&destination, content.as_bytes())
+                .with_context(|| format!("Writing manifest {:?}", destination))?;
+            output.produced_files.push(destination);
+        }
+        Ok(output)
+    }
+}
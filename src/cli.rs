@@ -0,0 +1,670 @@
+use crate::backup::{BackupOptions, BackupStore};
+use crate::error::ErrorCategory;
+use crate::export::{self, FileReport, ReportFormat};
+use crate::job::{Filters, JobSpec};
+use crate::output::{ConflictStrategy, OutputMode, OutputPolicy};
+use crate::pipeline::{Pipeline, StepSpec};
+use crate::processing_thread::FileProcessingThread;
+use crate::processor;
+use crate::results::FileResult;
+use crate::scan;
+use crate::watch::{FolderWatcher, WatchOptions};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{collections::HashSet, io, path::PathBuf, process::ExitCode};
+
+#[derive(Parser)]
+#[command(version, about = "Drag and drop file processor")]
+pub struct Args {
+    /// Run headless instead of opening the window
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0   every file succeeded
+  1   processor specific error
+  2   invalid arguments
+  10  I/O error
+  11  permission denied
+  12  file not found
+  13  invalid format
+  14  timeout
+  15  cancelled
+  16  processor panicked
+  17  checksum mismatch
+When files fail in different ways, the first of panicked, checksum mismatch, timeout,
+permission denied, not found, invalid format, I/O error, processor error and cancelled
+decides the code.";
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Process files with one of the built-in processors or a pipeline of them
+    #[command(after_help = EXIT_CODES_HELP)]
+    Process {
+        #[command(flatten)]
+        processor: ProcessorArgs,
+
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        scan: ScanArgs,
+
+        #[command(flatten)]
+        report: ReportArgs,
+
+        #[command(flatten)]
+        job: Box<JobArgs>,
+
+        /// Capture a backtrace for every failure and include it in the output
+        #[arg(long)]
+        backtrace: bool,
+
+        /// Print the changes processors would make as diffs instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Files or directories, directories are expanded recursively
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Watch folders and process files as they are created or modified, until interrupted
+    Watch {
+        #[command(flatten)]
+        processor: ProcessorArgs,
+
+        /// Output format, `json` prints one JSON object per line
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        scan: ScanArgs,
+
+        #[command(flatten)]
+        job: Box<JobArgs>,
+
+        #[command(flatten)]
+        watch: WatchArgs,
+
+        /// Directories to watch recursively
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+    },
+    /// List the available processors
+    Processors,
+    /// Restore the files the last job changed, leaving alone those changed since
+    Undo,
+}
+
+#[derive(clap::Args)]
+pub struct ProcessorArgs {
+    /// Processor name or slug, see the `processors` subcommand. Repeat to build
+    /// a pipeline whose steps run in the given order
+    #[arg(short, long = "processor", required_unless_present = "job_file")]
+    processors: Vec<String>,
+
+    /// Processor setting as KEY=VALUE, see the `processors` subcommand. Goes to
+    /// every step that takes KEY, or only to step N with N:KEY=VALUE (repeatable)
+    #[arg(long = "param", value_name = "[N:]KEY=VALUE", value_parser = parse_param)]
+    params: Vec<Param>,
+
+    /// Take the processor or pipeline, filters and worker settings from a TOML
+    /// job file. Filter and worker options given as well add to or override it
+    #[arg(
+        long = "job",
+        value_name = "FILE",
+        conflicts_with_all = ["processors", "params"]
+    )]
+    job_file: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+struct Param {
+    /// Step number, counted from one.
+    step: Option<usize>,
+    key: String,
+    value: String,
+}
+
+fn parse_param(param: &str) -> Result<Param, String> {
+    let Some((key, value)) = param.split_once('=') else {
+        return Err(String::from("expected KEY=VALUE"));
+    };
+    let (step, key) = match key.split_once(':') {
+        Some((step, key)) => match step.trim().parse() {
+            Ok(step) if step > 0 => (Some(step), key),
+            _ => return Err(format!("{step:?} is not a step number")),
+        },
+        None => (None, key),
+    };
+    Ok(Param {
+        step,
+        key: key.trim().to_owned(),
+        value: value.to_owned(),
+    })
+}
+
+impl ProcessorArgs {
+    /// The steps given by `-p` with their `--param` settings, or the error to print.
+    fn steps(&self) -> Result<Vec<StepSpec>, String> {
+        let mut steps: Vec<_> = self
+            .processors
+            .iter()
+            .map(|name| StepSpec::new(name.as_str()))
+            .collect();
+        for param in &self.params {
+            let targets: Vec<_> = match param.step {
+                Some(step) if step > steps.len() => {
+                    return Err(format!(
+                        "--param {}:{} targets a step that doesn't exist",
+                        step, param.key
+                    ));
+                }
+                Some(step) => vec![step - 1],
+                // Keys no step knows are left to `configure` to reject
+                None if steps.len() == 1 => vec![0],
+                None => (0..steps.len())
+                    .filter(|&index| takes_param(&steps[index], &param.key))
+                    .collect(),
+            };
+            if targets.is_empty() {
+                return Err(format!("No step takes the parameter {:?}", param.key));
+            }
+            for index in targets {
+                steps[index]
+                    .params
+                    .insert(param.key.clone(), param.value.clone());
+            }
+        }
+        Ok(steps)
+    }
+}
+
+fn takes_param(step: &StepSpec, key: &str) -> bool {
+    processor::find_processor(&step.processor)
+        .is_some_and(|processor| processor.params().iter().any(|spec| spec.key == key))
+}
+
+#[derive(clap::Args)]
+pub struct WatchArgs {
+    /// Only process a file once it saw no changes for this long
+    #[arg(long, default_value = "2s")]
+    settle: humantime::Duration,
+
+    /// Poll for changes instead of using inotify
+    #[arg(long)]
+    poll: bool,
+
+    /// How often to look for changes when polling
+    #[arg(long, default_value = "2s")]
+    poll_interval: humantime::Duration,
+}
+
+impl From<WatchArgs> for WatchOptions {
+    fn from(args: WatchArgs) -> Self {
+        WatchOptions {
+            settle: args.settle.into(),
+            force_polling: args.poll,
+            poll_interval: args.poll_interval.into(),
+        }
+    }
+}
+
+/// How the engine runs the files of a job.
+#[derive(clap::Args)]
+pub struct JobArgs {
+    #[command(flatten)]
+    pool: PoolArgs,
+
+    #[command(flatten)]
+    retry: RetryArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    /// Give up on any file still running after this long, e.g. `30s` or `2m`
+    #[arg(long)]
+    timeout: Option<humantime::Duration>,
+
+    /// Don't keep copies of the files a job replaces, which also means it can't be undone
+    #[arg(long)]
+    no_backup: bool,
+}
+
+impl JobArgs {
+    /// Overrides the settings of `spec` with the options that were given.
+    fn apply(&self, spec: &mut JobSpec) {
+        if self.pool.threads != 0 {
+            spec.threads = self.pool.threads;
+        }
+        spec.background_priority |= self.pool.background;
+        if self.retry.retries > 0 {
+            spec.retries = self.retry.retries;
+            spec.retry_backoff = Some(self.retry.retry_backoff.into());
+        }
+        if let Some(timeout) = self.timeout {
+            spec.timeout = Some(timeout.into());
+        }
+        self.output.apply(&mut spec.output);
+    }
+
+    fn backups(&self) -> Option<BackupStore> {
+        BackupStore::in_data_dir(BackupOptions {
+            enabled: !self.no_backup,
+            ..BackupOptions::default()
+        })
+    }
+}
+
+/// Where transformed files are written.
+#[derive(clap::Args)]
+pub struct OutputArgs {
+    /// Where transformed files are written, in place by default
+    #[arg(long, value_enum)]
+    output_mode: Option<OutputMode>,
+
+    /// Added to the names of transformed files, e.g. `.fixed` for notes.fixed.txt.
+    /// Implies `--output-mode suffix`
+    #[arg(long, conflicts_with = "output_dir")]
+    suffix: Option<String>,
+
+    /// Folder to recreate the given folders in, implies `--output-mode mirror`
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// What to do when an output other than the input itself already exists
+    #[arg(long, value_enum)]
+    on_conflict: Option<ConflictStrategy>,
+}
+
+impl OutputArgs {
+    fn apply(&self, policy: &mut OutputPolicy) {
+        if let Some(suffix) = &self.suffix {
+            policy.suffix = suffix.clone();
+            policy.mode = OutputMode::Suffix;
+        }
+        if let Some(dir) = &self.output_dir {
+            policy.dir = Some(dir.clone());
+            policy.mode = OutputMode::Mirror;
+        }
+        if let Some(mode) = self.output_mode {
+            policy.mode = mode;
+        }
+        if let Some(on_conflict) = self.on_conflict {
+            policy.on_conflict = on_conflict;
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct PoolArgs {
+    /// Number of worker threads, one per core by default
+    #[arg(short = 'j', long, default_value_t = 0)]
+    threads: usize,
+
+    /// Run workers at a lower OS scheduling priority (Linux only)
+    #[arg(long)]
+    background: bool,
+}
+
+#[derive(clap::Args)]
+pub struct RetryArgs {
+    /// Retry files failing with an I/O error or timing out reading up to this
+    /// many times. Files over --timeout are not retried
+    #[arg(long, default_value_t = 0)]
+    retries: u32,
+
+    /// Wait before the first retry, doubled for every further one
+    #[arg(long, default_value = "500ms", requires = "retries")]
+    retry_backoff: humantime::Duration,
+}
+
+#[derive(clap::Args)]
+pub struct ReportArgs {
+    /// Also write a report of the results to this file
+    #[arg(long)]
+    report: Option<PathBuf>,
+
+    /// Report format, guessed from the report file extension by default
+    #[arg(long, value_enum, requires = "report")]
+    report_format: Option<ReportFormat>,
+}
+
+#[derive(clap::Args)]
+pub struct ScanArgs {
+    /// Maximum directory depth to descend into, unlimited by default
+    #[arg(long)]
+    max_depth: Option<usize>,
+
+    /// Follow symbolic links while expanding directories
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Only take files matching this glob, relative to the directory (repeatable)
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// Skip files matching this glob, relative to the directory (repeatable)
+    #[arg(long)]
+    exclude: Vec<String>,
+}
+
+impl ScanArgs {
+    /// Adds the patterns to `filters` and overrides the options that were given.
+    fn apply(&self, filters: &mut Filters) {
+        if self.max_depth.is_some() {
+            filters.max_depth = self.max_depth;
+        }
+        filters.follow_symlinks |= self.follow_symlinks;
+        filters.include.extend(self.include.iter().cloned());
+        filters.exclude.extend(self.exclude.iter().cloned());
+    }
+}
+
+/// Combines the job file, if one was given, with the other options into the
+/// job to run and its pipeline, or the error to print.
+fn resolve_job(
+    processor: &ProcessorArgs,
+    scan: &ScanArgs,
+    job: &JobArgs,
+) -> Result<(JobSpec, Pipeline), String> {
+    let (mut spec, pipeline) = match &processor.job_file {
+        Some(path) => {
+            let spec = JobSpec::load(path).map_err(|e| format!("{e:#}"))?;
+            let pipeline = spec.pipeline().map_err(|e| format!("{e:#}"))?;
+            (spec, pipeline)
+        }
+        None => {
+            let steps = processor.steps()?;
+            let pipeline = Pipeline::from_specs(&steps).map_err(|e| format!("{e:#}"))?;
+            (JobSpec::new(steps), pipeline)
+        }
+    };
+    scan.apply(&mut spec.filters);
+    job.apply(&mut spec);
+    spec.output
+        .validate()
+        .map_err(|e| format!("Invalid output options: {e:#}"))?;
+    Ok((spec, pipeline))
+}
+
+/// An engine for the job, mirroring `roots` if the output policy says so.
+fn new_engine(
+    spec: &JobSpec,
+    job: &JobArgs,
+    pipeline: Pipeline,
+    roots: Vec<PathBuf>,
+) -> FileProcessingThread {
+    FileProcessingThread::new(pipeline)
+        .with_pool_options(spec.pool_options())
+        .with_retry_policy(spec.retry_policy())
+        .with_timeout(spec.timeout)
+        .with_output_policy(spec.output.clone(), roots)
+        .with_backups(job.backups())
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+pub fn run(command: Command) -> ExitCode {
+    match command {
+        Command::Process {
+            processor,
+            format,
+            scan,
+            report,
+            job,
+            backtrace,
+            dry_run,
+            files,
+        } => {
+            if backtrace {
+                // anyhow only captures backtraces when asked to through the environment
+                std::env::set_var("RUST_LIB_BACKTRACE", "1");
+            }
+            process(&processor, format, &scan, &job, report, dry_run, files)
+        }
+        Command::Watch {
+            processor,
+            format,
+            scan,
+            job,
+            watch,
+            dirs,
+        } => watch_folders(&processor, format, &scan, &job, watch.into(), dirs),
+        Command::Processors => {
+            for processor in processor::builtin_processors() {
+                println!(
+                    "{:<26} {}",
+                    processor::slug(processor.name()),
+                    processor.description()
+                );
+                for param in processor.params() {
+                    let setting = format!("{}={}", param.key, param.default);
+                    println!("{:<26}   --param {setting:<20} {}", "", param.description);
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Command::Undo => undo_last_job(),
+    }
+}
+
+/// Undoes the last job that wrote files, one tab separated line per file.
+fn undo_last_job() -> ExitCode {
+    let Some(store) = BackupStore::in_data_dir(BackupOptions::default()) else {
+        eprintln!("Error: No data folder to find backups in");
+        return ExitCode::FAILURE;
+    };
+    let job = match store.last_job() {
+        Ok(Some(job)) => job,
+        Ok(None) => {
+            eprintln!("No job to undo");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!(
+        "Undoing {} from {}",
+        job.name,
+        humantime::format_rfc3339_seconds(job.started)
+    );
+    let report = match job.undo() {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    for path in &report.restored {
+        println!("restored\t{}", path.display());
+    }
+    for path in &report.removed {
+        println!("removed\t{}", path.display());
+    }
+    for (path, reason) in &report.skipped {
+        println!("skipped\t{}\t{reason}", path.display());
+    }
+    eprintln!("{}", report.summary());
+    if report.skipped.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn process(
+    processor: &ProcessorArgs,
+    format: OutputFormat,
+    scan: &ScanArgs,
+    job: &JobArgs,
+    report: ReportArgs,
+    dry_run: bool,
+    paths: Vec<PathBuf>,
+) -> ExitCode {
+    let (spec, pipeline) = match resolve_job(processor, scan, job) {
+        Ok(job) => job,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let report_format = match (&report.report, report.report_format) {
+        (Some(_), Some(format)) => Some(format),
+        (Some(path), None) => match ReportFormat::from_path(path) {
+            Some(format) => Some(format),
+            None => {
+                eprintln!("Can't tell the report format of {path:?}, use --report-format");
+                return ExitCode::from(2);
+            }
+        },
+        (None, _) => None,
+    };
+    let files = match scan::expand_paths(&paths, &spec.scan_options()) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let roots = paths.iter().filter(|path| path.is_dir()).cloned().collect();
+    let mut file_processing_thread = new_engine(&spec, job, pipeline, roots).with_dry_run(dry_run);
+    file_processing_thread.set_file_list(files);
+    file_processing_thread.run();
+    file_processing_thread.wait();
+
+    let results = file_processing_thread.get_results();
+
+    match format {
+        OutputFormat::Text => results.iter().for_each(print_text),
+        OutputFormat::Json => {
+            if let Err(e) = export::write_report(&results, ReportFormat::Json, io::stdout().lock())
+            {
+                eprintln!("Error: {e:#}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if let (Some(path), Some(format)) = (&report.report, report_format) {
+        if let Err(e) = export::save_report(&results, format, path) {
+            eprintln!("Error: {e:#}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let mut categories: HashSet<_> = results.iter().filter_map(|r| r.error_category()).collect();
+    match file_processing_thread.get_job_output() {
+        Ok(output) => {
+            for file in &output.produced_files {
+                eprintln!("Wrote {}", file.display());
+            }
+            for (key, value) in &output.fields {
+                eprintln!("{key}: {value}");
+            }
+        }
+        Err(error) => {
+            eprintln!("Error: {}", error.message());
+            categories.insert(error.category);
+        }
+    }
+    match ErrorCategory::ALL.iter().find(|c| categories.contains(c)) {
+        Some(category) => ExitCode::from(category.exit_code()),
+        None => ExitCode::SUCCESS,
+    }
+}
+
+/// One tab separated line: status, path, duration and detail. Followed by
+/// the diffs of what a dry run would have changed.
+fn print_text(result: &FileResult) {
+    let status = match result.error_category() {
+        Some(category) if result.error.is_some() => {
+            format!("{}[{}]", result.status.label(), category.label())
+        }
+        _ => result.status.label().to_owned(),
+    };
+    println!(
+        "{}\t{}\t{:.2?}\t{}",
+        status,
+        result.path.display(),
+        result.duration,
+        result.detail()
+    );
+    for change in &result.changes {
+        match &change.diff {
+            Some(diff) => print!("{diff}"),
+            None => println!(
+                "Binary file {} would change from {} to {} bytes",
+                change.path.display(),
+                change.size_before,
+                change.size_after
+            ),
+        }
+    }
+}
+
+fn watch_folders(
+    processor: &ProcessorArgs,
+    format: OutputFormat,
+    scan: &ScanArgs,
+    job: &JobArgs,
+    watch_options: WatchOptions,
+    dirs: Vec<PathBuf>,
+) -> ExitCode {
+    let (spec, pipeline) = match resolve_job(processor, scan, job) {
+        Ok(job) => job,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let mut watcher = match FolderWatcher::new(dirs, &spec.scan_options(), watch_options, None) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            return ExitCode::from(2);
+        }
+    };
+    eprintln!(
+        "Watching {} folder(s) ({}), press Ctrl+C to stop",
+        watcher.roots().len(),
+        watcher.backend().label()
+    );
+
+    loop {
+        let files = watcher.wait();
+        for error in watcher.take_errors() {
+            eprintln!("Warning: {error}");
+        }
+        let files = match files {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Error: {e:#}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let roots = watcher.roots().to_vec();
+        let mut file_processing_thread = new_engine(&spec, job, pipeline.clone(), roots);
+        file_processing_thread.set_file_list(files);
+        file_processing_thread.run();
+        file_processing_thread.wait();
+        for result in &file_processing_thread.get_results() {
+            match format {
+                OutputFormat::Text => print_text(result),
+                OutputFormat::Json => match serde_json::to_string(&FileReport::from(result)) {
+                    Ok(line) => println!("{line}"),
+                    Err(e) => eprintln!("Error: {e:#}"),
+                },
+            }
+        }
+        watcher.ignore_written(file_processing_thread.written_files());
+    }
+}
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use thiserror::Error;
+
+/// Typed failures processors and the engine report, so callers can react to
+/// the kind of failure instead of matching on messages.
+#[derive(Debug, Error)]
+pub enum ProcessingError {
+    #[error("I/O error")]
+    Io(#[source] io::Error),
+    #[error("permission denied")]
+    PermissionDenied,
+    #[error("file not found")]
+    NotFound,
+    #[error("invalid format: {0}")]
+    InvalidFormat(String),
+    #[error("{algorithm} mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        algorithm: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("timed out")]
+    Timeout,
+    #[error("cancelled")]
+    Cancelled,
+    #[error("processor panicked: {0}")]
+    Panicked(String),
+    #[error("{0}")]
+    ProcessorSpecific(String),
+}
+
+impl ProcessingError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ProcessingError::Io(e) => ErrorCategory::from_io_kind(e.kind()),
+            ProcessingError::PermissionDenied => ErrorCategory::PermissionDenied,
+            ProcessingError::NotFound => ErrorCategory::NotFound,
+            ProcessingError::InvalidFormat(_) => ErrorCategory::InvalidFormat,
+            ProcessingError::ChecksumMismatch { .. } => ErrorCategory::ChecksumMismatch,
+            ProcessingError::Timeout => ErrorCategory::Timeout,
+            ProcessingError::Cancelled => ErrorCategory::Cancelled,
+            ProcessingError::Panicked(_) => ErrorCategory::Panicked,
+            ProcessingError::ProcessorSpecific(_) => ErrorCategory::ProcessorSpecific,
+        }
+    }
+}
+
+impl From<io::Error> for ProcessingError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => ProcessingError::NotFound,
+            io::ErrorKind::PermissionDenied => ProcessingError::PermissionDenied,
+            io::ErrorKind::TimedOut => ProcessingError::Timeout,
+            io::ErrorKind::InvalidData => ProcessingError::InvalidFormat(e.to_string()),
+            _ => ProcessingError::Io(e),
+        }
+    }
+}
+
+/// The kind of a `ProcessingError`, without its payload.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum ErrorCategory {
+    Io,
+    PermissionDenied,
+    NotFound,
+    InvalidFormat,
+    ChecksumMismatch,
+    Timeout,
+    Cancelled,
+    Panicked,
+    #[default]
+    ProcessorSpecific,
+}
+
+impl ErrorCategory {
+    /// In order of precedence when picking a single exit code for a job.
+    pub const ALL: [ErrorCategory; 9] = [
+        ErrorCategory::Panicked,
+        ErrorCategory::ChecksumMismatch,
+        ErrorCategory::Timeout,
+        ErrorCategory::PermissionDenied,
+        ErrorCategory::NotFound,
+        ErrorCategory::InvalidFormat,
+        ErrorCategory::Io,
+        ErrorCategory::ProcessorSpecific,
+        ErrorCategory::Cancelled,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ErrorCategory::Io => "I/O error",
+            ErrorCategory::PermissionDenied => "permission denied",
+            ErrorCategory::NotFound => "not found",
+            ErrorCategory::InvalidFormat => "invalid format",
+            ErrorCategory::ChecksumMismatch => "checksum mismatch",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Cancelled => "cancelled",
+            ErrorCategory::Panicked => "panicked",
+            ErrorCategory::ProcessorSpecific => "processor error",
+        }
+    }
+
+    /// Process exit code used by the command line when a file fails this way.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ErrorCategory::ProcessorSpecific => 1,
+            ErrorCategory::Io => 10,
+            ErrorCategory::PermissionDenied => 11,
+            ErrorCategory::NotFound => 12,
+            ErrorCategory::InvalidFormat => 13,
+            ErrorCategory::Timeout => 14,
+            ErrorCategory::Cancelled => 15,
+            ErrorCategory::Panicked => 16,
+            ErrorCategory::ChecksumMismatch => 17,
+        }
+    }
+
+    /// Failures that may go away on their own, such as a file locked by another
+    /// program or a flaky network share, and are worth retrying.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ErrorCategory::Io | ErrorCategory::Timeout)
+    }
+
+    fn from_io_kind(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::NotFound => ErrorCategory::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorCategory::PermissionDenied,
+            io::ErrorKind::TimedOut => ErrorCategory::Timeout,
+            io::ErrorKind::InvalidData => ErrorCategory::InvalidFormat,
+            _ => ErrorCategory::Io,
+        }
+    }
+
+    /// Finds the most specific category anywhere in the error chain.
+    ///
+    /// Typed `ProcessingError`s win over plain I/O errors, and anything
+    /// unrecognised counts as processor specific.
+    pub fn classify(error: &anyhow::Error) -> Self {
+        if let Some(e) = error
+            .chain()
+            .find_map(|e| e.downcast_ref::<ProcessingError>())
+        {
+            return e.category();
+        }
+        if let Some(e) = error.chain().find_map(|e| e.downcast_ref::<io::Error>()) {
+            return Self::from_io_kind(e.kind());
+        }
+        ErrorCategory::ProcessorSpecific
+    }
+}
@@ -0,0 +1,220 @@
+use crate::error::ErrorCategory;
+use crate::processor::ProcessOutput;
+use crate::results::{ErrorReport, FileResult, PendingChange, ResultStatus, StepResult};
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReportFormat {
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl ReportFormat {
+    pub const ALL: [ReportFormat; 3] = [
+        ReportFormat::Json,
+        ReportFormat::Csv,
+        ReportFormat::Markdown,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "JSON",
+            ReportFormat::Csv => "CSV",
+            ReportFormat::Markdown => "Markdown",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Markdown => "md",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(ReportFormat::Json),
+            "csv" => Some(ReportFormat::Csv),
+            "md" | "markdown" => Some(ReportFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// Serialized shape of a single result, shared by the JSON report and the CLI.
+#[derive(Serialize)]
+pub struct FileReport<'a> {
+    path: &'a PathBuf,
+    status: &'static str,
+    duration_ms: u128,
+    attempts: u32,
+    output: &'a ProcessOutput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a ErrorReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    steps: Vec<StepReport<'a>>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    changes: &'a [PendingChange],
+}
+
+/// Serialized shape of one pipeline step of a result.
+#[derive(Serialize)]
+pub struct StepReport<'a> {
+    processor: &'a str,
+    status: &'static str,
+    duration_ms: u128,
+    output: &'a ProcessOutput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a ErrorReport>,
+}
+
+impl<'a> From<&'a StepResult> for StepReport<'a> {
+    fn from(step: &'a StepResult) -> Self {
+        StepReport {
+            processor: &step.processor,
+            status: step.status.label(),
+            duration_ms: step.duration.as_millis(),
+            output: &step.output,
+            error: step.error.as_ref(),
+        }
+    }
+}
+
+impl<'a> From<&'a FileResult> for FileReport<'a> {
+    fn from(result: &'a FileResult) -> Self {
+        FileReport {
+            path: &result.path,
+            status: result.status.label(),
+            duration_ms: result.duration.as_millis(),
+            attempts: result.attempts,
+            output: &result.output,
+            error: result.error.as_ref(),
+            steps: result.steps.iter().map(StepReport::from).collect(),
+            changes: &result.changes,
+        }
+    }
+}
+
+pub fn write_report(
+    results: &[FileResult],
+    format: ReportFormat,
+    writer: impl Write,
+) -> Result<()> {
+    match format {
+        ReportFormat::Json => write_json(results, writer),
+        ReportFormat::Csv => write_csv(results, writer),
+        ReportFormat::Markdown => write_markdown(results, writer),
+    }
+}
+
+pub fn save_report(results: &[FileResult], format: ReportFormat, path: &Path) -> Result<()> {
+    let file = fs::File::create(path).with_context(|| format!("Creating report {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    write_report(results, format, &mut writer)?;
+    writer
+        .flush()
+        .with_context(|| format!("Writing report {:?}", path))
+}
+
+fn write_json(results: &[FileResult], mut writer: impl Write) -> Result<()> {
+    let reports: Vec<FileReport> = results.iter().map(FileReport::from).collect();
+    serde_json::to_writer_pretty(&mut writer, &reports)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+fn write_csv(results: &[FileResult], writer: impl Write) -> Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record([
+        "path",
+        "status",
+        "duration_ms",
+        "attempts",
+        "fields",
+        "produced_files",
+        "text",
+        "error_category",
+        "error",
+        "failed_step",
+    ])?;
+    for result in results {
+        let fields: Vec<_> = result
+            .output
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        let produced_files: Vec<_> = result
+            .output
+            .produced_files
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect();
+        csv.write_record([
+            result.path.display().to_string(),
+            result.status.label().to_owned(),
+            result.duration.as_millis().to_string(),
+            result.attempts.to_string(),
+            fields.join("; "),
+            produced_files.join("; "),
+            result.output.text.clone().unwrap_or_default(),
+            result
+                .error_category()
+                .map(|c| c.label().to_owned())
+                .unwrap_or_default(),
+            result.error_message().unwrap_or_default(),
+            result
+                .failed_step()
+                .map(|(number, step)| format!("{number} {}", step.processor))
+                .unwrap_or_default(),
+        ])?;
+    }
+    csv.flush()?;
+    Ok(())
+}
+
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', "<br>")
+}
+
+fn write_markdown(results: &[FileResult], mut writer: impl Write) -> Result<()> {
+    writeln!(writer, "# Processing report")?;
+    writeln!(writer)?;
+    for status in ResultStatus::ALL {
+        let count = results.iter().filter(|r| r.status == status).count();
+        writeln!(writer, "- **{}**: {count}", status.label())?;
+    }
+    for category in ErrorCategory::ALL {
+        let count = results
+            .iter()
+            .filter(|r| r.error_category() == Some(category))
+            .count();
+        if count > 0 {
+            writeln!(writer, "  - {}: {count}", category.label())?;
+        }
+    }
+    writeln!(writer)?;
+    writeln!(writer, "| Status | File | Duration | Output |")?;
+    writeln!(writer, "| --- | --- | --- | --- |")?;
+    for result in results {
+        writeln!(
+            writer,
+            "| {} | `{}` | {:.2?} | {} |",
+            result.status.label(),
+            escape_markdown_cell(&result.path.display().to_string()),
+            result.duration,
+            escape_markdown_cell(&result.detail())
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,283 @@
+use crate::output::OutputPolicy;
+use crate::pipeline::{Pipeline, StepSpec};
+use crate::processor::{self, Params};
+use crate::retry::RetryPolicy;
+use crate::scan::{PathFilter, ScanOptions};
+use crate::worker_pool::WorkerPoolOptions;
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, time::Duration};
+
+/// Everything that defines a job, in the shape of a shareable TOML file:
+///
+/// ```toml
+/// threads = 4
+/// timeout = "30s"
+///
+/// [[pipeline]]
+/// processor = "normalize-line-endings"
+///
+/// [[pipeline]]
+/// processor = "checksum"
+/// params = { algorithms = "sha256,md5" }
+///
+/// [filters]
+/// include = ["*.txt"]
+///
+/// [output]
+/// mode = "mirror"
+/// dir = "processed"  # next to the job file
+/// on_conflict = "rename"
+/// ```
+///
+/// A single processor can be given as `processor` and `params` instead of a pipeline.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobSpec {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processor: Option<String>,
+    #[serde(skip_serializing_if = "Params::is_empty")]
+    pub params: Params,
+    /// `0` uses one thread per logical core.
+    pub threads: usize,
+    pub background_priority: bool,
+    #[serde(with = "duration_text", skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+    /// Further tries for files failing with a transient error.
+    pub retries: u32,
+    #[serde(with = "duration_text", skip_serializing_if = "Option::is_none")]
+    pub retry_backoff: Option<Duration>,
+    pub filters: Filters,
+    /// Where transformed files are written.
+    pub output: OutputPolicy,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pipeline: Vec<StepSpec>,
+}
+
+/// Which files of dropped or given folders the job takes.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filters {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+}
+
+/// Durations as humantime text such as `30s` or `1m 30s`.
+mod duration_text {
+    use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => {
+                serializer.serialize_str(&humantime::format_duration(*duration).to_string())
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        humantime::parse_duration(&text)
+            .map(Some)
+            .map_err(|e| D::Error::custom(format!("{text:?} is not a duration like 30s: {e}")))
+    }
+}
+
+impl JobSpec {
+    /// A job running `steps`, written as a plain `processor` when there is only one.
+    pub fn new(mut steps: Vec<StepSpec>) -> Self {
+        if steps.len() == 1 {
+            let step = steps.remove(0);
+            return JobSpec {
+                processor: Some(step.processor),
+                params: step.params,
+                ..JobSpec::default()
+            };
+        }
+        JobSpec {
+            pipeline: steps,
+            ..JobSpec::default()
+        }
+    }
+
+    pub fn with_scan_options(mut self, options: &ScanOptions) -> Self {
+        self.filters = Filters {
+            include: options.include.clone(),
+            exclude: options.exclude.clone(),
+            max_depth: options.max_depth,
+            follow_symlinks: options.follow_symlinks,
+        };
+        self
+    }
+
+    pub fn with_pool_options(mut self, options: WorkerPoolOptions) -> Self {
+        self.threads = options.threads;
+        self.background_priority = options.background_priority;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retries = retry.max_attempts.saturating_sub(1);
+        self.retry_backoff = retry.is_enabled().then_some(retry.initial_backoff);
+        self
+    }
+
+    pub fn with_output_policy(mut self, policy: OutputPolicy) -> Self {
+        self.output = policy;
+        self
+    }
+
+    pub fn has_job_extension(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("toml"))
+    }
+
+    /// Whether `path` should be loaded as a job rather than processed: a
+    /// TOML file naming a `processor` or `pipeline`, unlike e.g. `Cargo.toml`.
+    pub fn is_job_file(path: &Path) -> bool {
+        if !Self::has_job_extension(path) {
+            return false;
+        }
+        let Ok(text) = fs::read_to_string(path) else {
+            return false;
+        };
+        text.parse::<toml::Table>()
+            .is_ok_and(|table| table.contains_key("processor") || table.contains_key("pipeline"))
+    }
+
+    /// Reads and validates a job file. Relative paths in it are taken from
+    /// the folder of the job file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Reading job {:?}", path))?;
+        let mut spec = Self::parse(&text).with_context(|| format!("Invalid job {:?}", path))?;
+        if let (Some(dir), Some(folder)) = (&mut spec.output.dir, path.parent()) {
+            if dir.is_relative() {
+                *dir = folder.join(&*dir);
+            }
+        }
+        Ok(spec)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let spec: JobSpec = toml::from_str(text)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = toml::to_string_pretty(self).context("Serializing the job")?;
+        fs::write(path, text).with_context(|| format!("Writing job {:?}", path))
+    }
+
+    /// Checks what TOML itself can't, naming the offending key in every error.
+    pub fn validate(&self) -> Result<()> {
+        match (&self.processor, self.pipeline.is_empty()) {
+            (Some(_), false) => anyhow::bail!("`processor` and `pipeline` can't both be given"),
+            (None, true) => anyhow::bail!("Either `processor` or `pipeline` is needed"),
+            (None, false) if !self.params.is_empty() => anyhow::bail!(
+                "`params` only goes with `processor`, give every pipeline step its own"
+            ),
+            _ => {}
+        }
+        self.pipeline()?;
+        PathFilter::new(&self.scan_options()).context("In `filters`")?;
+        self.output.validate().context("In `output`")?;
+        Ok(())
+    }
+
+    /// The steps to run, a single one for a plain `processor`.
+    pub fn steps(&self) -> Vec<StepSpec> {
+        match &self.processor {
+            Some(processor) => vec![StepSpec {
+                processor: processor.clone(),
+                params: self.params.clone(),
+            }],
+            None => self.pipeline.clone(),
+        }
+    }
+
+    /// Looks up and configures every step.
+    pub fn pipeline(&self) -> Result<Pipeline> {
+        let steps = self.steps();
+        let keys: Vec<_> = match &self.processor {
+            Some(_) => vec![(String::from("processor"), String::from("params"))],
+            None => (0..steps.len())
+                .map(|index| {
+                    (
+                        format!("pipeline[{index}].processor"),
+                        format!("pipeline[{index}].params"),
+                    )
+                })
+                .collect(),
+        };
+        let mut processors = Vec::with_capacity(steps.len());
+        for (step, (processor_key, params_key)) in steps.iter().zip(keys) {
+            let Some(processor) = processor::find_processor(&step.processor) else {
+                anyhow::bail!(
+                    "`{processor_key}`: unknown processor {:?}, see `processors` for the list",
+                    step.processor
+                );
+            };
+            let specs = processor.params();
+            if let Some(key) = step
+                .params
+                .keys()
+                .find(|key| !specs.iter().any(|spec| spec.key == key.as_str()))
+            {
+                let known: Vec<_> = specs.iter().map(|spec| spec.key).collect();
+                anyhow::bail!(
+                    "`{params_key}.{key}`: {} takes {}",
+                    processor.name(),
+                    if known.is_empty() {
+                        String::from("no parameters")
+                    } else {
+                        format!("only {}", known.join(", "))
+                    }
+                );
+            }
+            let processor = processor::configure(&processor, &step.params)
+                .with_context(|| format!("In `{params_key}`"))?;
+            processors.push(processor);
+        }
+        Ok(Pipeline::new(processors))
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            max_depth: self.filters.max_depth,
+            follow_symlinks: self.filters.follow_symlinks,
+            include: self.filters.include.clone(),
+            exclude: self.filters.exclude.clone(),
+        }
+    }
+
+    pub fn pool_options(&self) -> WorkerPoolOptions {
+        WorkerPoolOptions {
+            threads: self.threads,
+            background_priority: self.background_priority,
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.retries.saturating_add(1),
+            initial_backoff: self.retry_backoff.unwrap_or(default.initial_backoff),
+            ..default
+        }
+    }
+}
@@ -0,0 +1,666 @@
+use crate::backup::JobBackup;
+use crate::error::ProcessingError;
+use anyhow::{Context as _, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+/// Where the content processors transform files into is written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputMode {
+    /// Replace the input file
+    #[default]
+    InPlace,
+    /// Write next to the input, with the suffix added to its name
+    Suffix,
+    /// Recreate the input folders below an output directory
+    Mirror,
+}
+
+impl OutputMode {
+    pub const ALL: [OutputMode; 3] = [OutputMode::InPlace, OutputMode::Suffix, OutputMode::Mirror];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutputMode::InPlace => "In place",
+            OutputMode::Suffix => "Next to the input",
+            OutputMode::Mirror => "Mirror into folder",
+        }
+    }
+}
+
+/// What happens when an output that isn't the input itself already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    /// Replace the existing file
+    #[default]
+    Overwrite,
+    /// Leave the existing file alone and write nothing
+    Skip,
+    /// Write to a free name such as `notes (1).txt` instead
+    Rename,
+}
+
+impl ConflictStrategy {
+    pub const ALL: [ConflictStrategy; 3] = [
+        ConflictStrategy::Overwrite,
+        ConflictStrategy::Skip,
+        ConflictStrategy::Rename,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConflictStrategy::Overwrite => "Overwrite",
+            ConflictStrategy::Skip => "Skip",
+            ConflictStrategy::Rename => "Rename",
+        }
+    }
+}
+
+/// How a job writes the files its processors transform.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputPolicy {
+    pub mode: OutputMode,
+    /// Added to the file stem in `Suffix` mode, `notes.txt` becomes `notes.processed.txt`.
+    pub suffix: String,
+    /// The folder `Mirror` mode writes to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    pub on_conflict: ConflictStrategy,
+}
+
+impl Default for OutputPolicy {
+    fn default() -> Self {
+        OutputPolicy {
+            mode: OutputMode::InPlace,
+            suffix: String::from(".processed"),
+            dir: None,
+            on_conflict: ConflictStrategy::Overwrite,
+        }
+    }
+}
+
+impl OutputPolicy {
+    pub fn validate(&self) -> Result<()> {
+        match self.mode {
+            OutputMode::InPlace => {}
+            OutputMode::Suffix if self.suffix.is_empty() => {
+                anyhow::bail!("`suffix` can't be empty, the output would replace the input")
+            }
+            OutputMode::Suffix if self.suffix.contains(['/', '\\']) => {
+                anyhow::bail!("`suffix` {:?} can't contain a path separator", self.suffix)
+            }
+            OutputMode::Suffix => {}
+            OutputMode::Mirror if self.dir.is_none() => {
+                anyhow::bail!("`dir` is needed to mirror the inputs into")
+            }
+            OutputMode::Mirror => {}
+        }
+        Ok(())
+    }
+}
+
+/// The output policy of one job, resolved against the folders its files came from.
+pub struct OutputTarget {
+    policy: OutputPolicy,
+    /// Absolute paths of the dropped or given folders.
+    roots: Vec<PathBuf>,
+    backup: Option<JobBackup>,
+    /// Absolute destinations handed out so far, with the input each is for.
+    claimed: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl OutputTarget {
+    pub fn new(policy: OutputPolicy, roots: &[PathBuf]) -> Result<Self> {
+        policy.validate()?;
+        Ok(OutputTarget {
+            policy,
+            roots: roots.iter().map(|root| absolute(root)).collect(),
+            backup: None,
+            claimed: Mutex::default(),
+        })
+    }
+
+    /// Backs up every file before it is first written, see `JobBackup::write`.
+    pub fn with_backup(mut self, backup: JobBackup) -> Self {
+        self.backup = Some(backup);
+        self
+    }
+
+    pub fn is_in_place(&self) -> bool {
+        self.policy.mode == OutputMode::InPlace
+    }
+
+    /// Where the output for `input` goes, before conflicts are resolved.
+    ///
+    /// Mirrored files keep their path below the folder they were found in,
+    /// under that folder's name. Loose files land in the output folder itself.
+    pub fn destination(&self, input: &Path) -> PathBuf {
+        match (&self.policy.mode, &self.policy.dir) {
+            (OutputMode::Suffix, _) => {
+                let mut name = input.file_stem().unwrap_or_default().to_os_string();
+                name.push(&self.policy.suffix);
+                if let Some(extension) = input.extension() {
+                    name.push(".");
+                    name.push(extension);
+                }
+                input.with_file_name(name)
+            }
+            (OutputMode::Mirror, Some(dir)) => {
+                let input = absolute(input);
+                let relative = self
+                    .roots
+                    .iter()
+                    .filter_map(|root| Some((root, input.strip_prefix(root).ok()?)))
+                    .max_by_key(|(root, _)| root.as_os_str().len())
+                    .map(|(root, relative)| match root.file_name() {
+                        Some(name) => Path::new(name).join(relative),
+                        None => relative.to_path_buf(),
+                    });
+                let relative = relative
+                    .unwrap_or_else(|| input.file_name().map(PathBuf::from).unwrap_or_default());
+                dir.join(relative)
+            }
+            _ => input.to_path_buf(),
+        }
+    }
+
+    /// The file to write the output for `input` to, or `None` if it exists
+    /// and the policy is to skip it. Two inputs of the job that end up with
+    /// the same destination conflict as well.
+    pub fn resolve(&self, input: &Path) -> Result<Option<PathBuf>> {
+        let destination = self.destination(input);
+        if self.is_in_place() {
+            return Ok(Some(destination));
+        }
+        self.claim(destination, input)
+    }
+
+    /// Whether `path` looks like the output of an earlier run, which is not
+    /// processed again so outputs don't pile up on every run.
+    pub fn is_output(&self, path: &Path) -> bool {
+        match (&self.policy.mode, &self.policy.dir) {
+            // The suffix ends the stem, or the whole name for inputs without extension
+            (OutputMode::Suffix, _) => [path.file_stem(), path.file_name()]
+                .into_iter()
+                .flatten()
+                .any(|name| {
+                    let name = name.to_string_lossy();
+                    without_copy_number(&name).ends_with(&self.policy.suffix)
+                }),
+            (OutputMode::Mirror, Some(dir)) => absolute(path).starts_with(absolute(dir)),
+            _ => false,
+        }
+    }
+
+    /// Atomically writes `content` to `destination`, creating the folders a
+    /// mirrored file needs.
+    pub fn write(&self, destination: &Path, content: &[u8]) -> Result<()> {
+        if let Some(parent) = destination.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(ProcessingError::from)
+                .with_context(|| format!("Creating {:?}", parent))?;
+        }
+        match &self.backup {
+            Some(backup) => backup.write(destination, content),
+            None => write_atomic(destination, content),
+        }
+    }
+
+    /// Where a file the job produces as a whole, e.g. a manifest, goes
+    /// instead of `path` if that exists, or `None` to skip it. Unlike
+    /// `resolve`, in place jobs also keep to the conflict strategy.
+    pub fn resolve_job_output(&self, path: &Path) -> Result<Option<PathBuf>> {
+        self.claim(path.to_path_buf(), path)
+    }
+
+    /// Reserves `destination` for the output of `owner`, or what the conflict
+    /// strategy picks instead if it exists or belongs to another output of the job.
+    fn claim(&self, destination: PathBuf, owner: &Path) -> Result<Option<PathBuf>> {
+        let mut claimed = self.claimed.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = absolute(&destination);
+        let other_owner = match claimed.get(&key) {
+            // A retry of the same file
+            Some(claimed_by) if claimed_by == owner => return Ok(Some(destination)),
+            Some(_) => true,
+            None => false,
+        };
+        let destination = if !other_owner && !destination.exists() {
+            destination
+        } else {
+            match self.policy.on_conflict {
+                ConflictStrategy::Overwrite if other_owner => {
+                    return Err(ProcessingError::ProcessorSpecific(format!(
+                        "{} is the output of another file of the job too",
+                        destination.display()
+                    ))
+                    .into());
+                }
+                ConflictStrategy::Overwrite => destination,
+                ConflictStrategy::Skip => return Ok(None),
+                ConflictStrategy::Rename => free_name(&destination, |candidate| {
+                    candidate.exists() || claimed.contains_key(&absolute(candidate))
+                }),
+            }
+        };
+        claimed.insert(absolute(&destination), owner.to_path_buf());
+        Ok(Some(destination))
+    }
+
+    /// Writes a file the job produces as a whole to the `destination` that
+    /// `resolve_job_output` picked, once there is room for it.
+    pub fn write_job_output(&self, destination: &Path, content: &[u8]) -> Result<()> {
+        self.check_room([(destination.to_path_buf(), content.len() as u64)], false)?;
+        self.write(destination, content)
+    }
+
+    /// Fails if a file system the outputs of `files` go to lacks room for
+    /// them: all of their sizes for new files, or the largest one for the
+    /// temporary copy of an in place write, plus the backups of the files
+    /// that get replaced. Only checked on Unix and Windows.
+    pub fn check_free_space(&self, files: &[PathBuf]) -> Result<()> {
+        let writes = files.iter().filter_map(|file| {
+            let metadata = fs::metadata(file).ok()?;
+            Some((self.destination(file), metadata.len()))
+        });
+        self.check_room(writes, self.is_in_place())
+    }
+
+    /// Checks there is room for writing each destination with the given
+    /// number of bytes, see `check_free_space`.
+    fn check_room(
+        &self,
+        writes: impl IntoIterator<Item = (PathBuf, u64)>,
+        in_place: bool,
+    ) -> Result<()> {
+        let mut spaces: HashMap<PathBuf, Option<FileSystemSpace>> = HashMap::new();
+        let mut needed: HashMap<u64, Needed> = HashMap::new();
+        let mut need = |path: &Path, bytes: u64, largest_only: bool| {
+            let Some(dir) = path.ancestors().find(|dir| dir.is_dir()) else {
+                return;
+            };
+            let space = *spaces
+                .entry(dir.to_path_buf())
+                .or_insert_with(|| FileSystemSpace::of(dir));
+            let Some(space) = space else {
+                return;
+            };
+            let needed = needed.entry(space.id).or_insert_with(|| Needed {
+                dir: dir.to_path_buf(),
+                available: space.available,
+                total: 0,
+                largest: 0,
+            });
+            if largest_only {
+                needed.largest = needed.largest.max(bytes);
+            } else {
+                needed.total += bytes;
+            }
+        };
+        for (destination, bytes) in writes {
+            let destination = absolute(&destination);
+            if let Some(parent) = destination.parent() {
+                need(parent, bytes, in_place);
+            }
+            let replaced = fs::metadata(&destination).map_or(0, |m| m.len());
+            if let Some(backup) = self.backup.as_ref().filter(|_| replaced > 0) {
+                need(backup.dir(), replaced, false);
+            }
+        }
+        for needed in needed.into_values() {
+            let bytes = needed.total + needed.largest;
+            if bytes > needed.available {
+                let message = format!(
+                    "Not enough free disk space for the output in {:?}: {} needed, {} available",
+                    needed.dir,
+                    mebibytes(bytes),
+                    mebibytes(needed.available)
+                );
+                return Err(
+                    ProcessingError::Io(io::Error::new(io::ErrorKind::Other, message)).into(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a job may write to one file system.
+struct Needed {
+    /// A folder on the file system, for the error message.
+    dir: PathBuf,
+    available: u64,
+    /// Bytes of files that are all kept.
+    total: u64,
+    /// Bytes of the largest temporary file, which only exists for a moment.
+    largest: u64,
+}
+
+/// Free space on the file system holding a folder.
+#[derive(Clone, Copy)]
+struct FileSystemSpace {
+    id: u64,
+    available: u64,
+}
+
+impl FileSystemSpace {
+    #[cfg(unix)]
+    fn of(dir: &Path) -> Option<Self> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+        // SAFETY: `path` is NUL terminated and `stats` is only read once statvfs filled it in
+        let stats = unsafe {
+            if libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) != 0 {
+                return None;
+            }
+            stats.assume_init()
+        };
+        Some(FileSystemSpace {
+            id: stats.f_fsid as u64,
+            available: stats.f_bavail as u64 * stats.f_frsize as u64,
+        })
+    }
+
+    /// Volumes are told apart by their mount point, e.g. `C:\`.
+    #[cfg(windows)]
+    fn of(dir: &Path) -> Option<Self> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        use std::os::windows::ffi::OsStrExt;
+
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetVolumePathNameW(file_name: *const u16, volume: *mut u16, length: u32) -> i32;
+            fn GetDiskFreeSpaceExW(
+                directory: *const u16,
+                available: *mut u64,
+                total: *mut u64,
+                free: *mut u64,
+            ) -> i32;
+        }
+
+        let path: Vec<u16> = dir.as_os_str().encode_wide().chain(Some(0)).collect();
+        let mut volume = [0u16; 1024];
+        let mut available = 0u64;
+        // SAFETY: `path` is NUL terminated, and `volume` and `available` outlive the
+        // calls, with the length of `volume` passed along
+        unsafe {
+            if GetVolumePathNameW(path.as_ptr(), volume.as_mut_ptr(), volume.len() as u32) == 0
+                || GetDiskFreeSpaceExW(
+                    path.as_ptr(),
+                    &mut available,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                ) == 0
+            {
+                return None;
+            }
+        }
+        let length = volume.iter().position(|&c| c == 0).unwrap_or(volume.len());
+        let mut hasher = DefaultHasher::new();
+        volume[..length].hash(&mut hasher);
+        Some(FileSystemSpace {
+            id: hasher.finish(),
+            available,
+        })
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn of(_dir: &Path) -> Option<Self> {
+        None
+    }
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// `name` without the ` (N)` that `free_name` adds.
+fn without_copy_number(name: &str) -> &str {
+    match name.rsplit_once(" (") {
+        Some((base, n))
+            if n.strip_suffix(')')
+                .is_some_and(|n| n.parse::<u32>().is_ok()) =>
+        {
+            base
+        }
+        _ => name,
+    }
+}
+
+/// `path` with ` (1)`, ` (2)` and so on added to its stem, whichever isn't
+/// `taken` first.
+fn free_name(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    (1..)
+        .map(|n| {
+            let name = match path.extension() {
+                Some(extension) => format!("{stem} ({n}).{}", extension.to_string_lossy()),
+                None => format!("{stem} ({n})"),
+            };
+            path.with_file_name(name)
+        })
+        .find(|candidate| !taken(candidate))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+pub fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    env::current_dir().map_or_else(|_| path.to_path_buf(), |dir| dir.join(path))
+}
+
+static TEMP_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Writes `content` to a temporary file next to `path` and renames it over
+/// `path`, so nobody ever sees a half written file. A symlink is followed
+/// and the file it points to replaced, keeping its permissions.
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let path = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::canonicalize(path)
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Resolving {:?}", path))?,
+        _ => path.to_path_buf(),
+    };
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(
+        ".{name}.{}-{}.tmp",
+        process::id(),
+        TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written =
+        write_new(&temp, content, fs::metadata(&path).ok()).and_then(|()| fs::rename(&temp, &path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(ProcessingError::from(e)).with_context(|| format!("Writing {:?}", path));
+    }
+    Ok(())
+}
+
+fn write_new(path: &Path, content: &[u8], original: Option<fs::Metadata>) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(content)?;
+    if let Some(original) = original {
+        file.set_permissions(original.permissions())?;
+    }
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(mode: OutputMode, dir: Option<&str>, roots: &[&str]) -> OutputTarget {
+        let policy = OutputPolicy {
+            mode,
+            dir: dir.map(PathBuf::from),
+            ..OutputPolicy::default()
+        };
+        let roots: Vec<_> = roots.iter().map(PathBuf::from).collect();
+        OutputTarget::new(policy, &roots).unwrap()
+    }
+
+    /// An empty folder of its own for every test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("output-test-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn suffix_goes_before_the_extension() {
+        let target = target(OutputMode::Suffix, None, &[]);
+        assert_eq!(
+            target.destination(Path::new("docs/notes.txt")),
+            Path::new("docs/notes.processed.txt")
+        );
+        assert_eq!(
+            target.destination(Path::new("docs/archive.tar.gz")),
+            Path::new("docs/archive.tar.processed.gz")
+        );
+        assert_eq!(
+            target.destination(Path::new("Makefile")),
+            Path::new("Makefile.processed")
+        );
+    }
+
+    #[test]
+    fn mirror_keeps_the_path_below_the_closest_root() {
+        let target = target(
+            OutputMode::Mirror,
+            Some("/out"),
+            &["/data/in", "/data/in/nested"],
+        );
+        assert_eq!(
+            target.destination(Path::new("/data/in/nested/a/b.txt")),
+            Path::new("/out/nested/a/b.txt")
+        );
+        assert_eq!(
+            target.destination(Path::new("/data/in/a/b.txt")),
+            Path::new("/out/in/a/b.txt")
+        );
+        // Loose files land in the output folder itself
+        assert_eq!(
+            target.destination(Path::new("/elsewhere/c.txt")),
+            Path::new("/out/c.txt")
+        );
+    }
+
+    #[test]
+    fn in_place_writes_the_input() {
+        let target = target(OutputMode::InPlace, None, &[]);
+        assert_eq!(target.destination(Path::new("a.txt")), Path::new("a.txt"));
+        assert!(!target.is_output(Path::new("a.processed.txt")));
+    }
+
+    #[test]
+    fn recognizes_suffixed_outputs() {
+        let target = target(OutputMode::Suffix, None, &[]);
+        assert!(target.is_output(Path::new("notes.processed.txt")));
+        assert!(target.is_output(Path::new("notes.processed (2).txt")));
+        assert!(target.is_output(Path::new("Makefile.processed")));
+        assert!(target.is_output(Path::new("Makefile (1).processed")));
+        assert!(!target.is_output(Path::new("notes.txt")));
+        assert!(!target.is_output(Path::new("notes (2).txt")));
+        assert!(!target.is_output(Path::new("notes.processed (two).txt")));
+    }
+
+    #[test]
+    fn recognizes_mirrored_outputs() {
+        let target = target(OutputMode::Mirror, Some("/out"), &["/data"]);
+        assert!(target.is_output(Path::new("/out/data/a.txt")));
+        assert!(!target.is_output(Path::new("/data/a.txt")));
+        assert!(!target.is_output(Path::new("/outside/a.txt")));
+    }
+
+    #[test]
+    fn free_name_counts_up() {
+        let dir = temp_dir("free-name");
+        fs::write(dir.join("a.txt"), "").unwrap();
+        fs::write(dir.join("a (1).txt"), "").unwrap();
+        fs::write(dir.join("SHA256SUMS"), "").unwrap();
+        let exists = |path: &Path| path.exists();
+        assert_eq!(free_name(&dir.join("a.txt"), exists), dir.join("a (2).txt"));
+        assert_eq!(
+            free_name(&dir.join("SHA256SUMS"), exists),
+            dir.join("SHA256SUMS (1)")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn conflicts_follow_the_strategy() {
+        let dir = temp_dir("conflicts");
+        let input = dir.join("a.txt");
+        fs::write(&input, "").unwrap();
+        let mut policy = OutputPolicy {
+            mode: OutputMode::Suffix,
+            ..OutputPolicy::default()
+        };
+        let resolve = |policy: &OutputPolicy| {
+            OutputTarget::new(policy.clone(), &[])
+                .unwrap()
+                .resolve(&input)
+                .unwrap()
+        };
+
+        assert_eq!(resolve(&policy), Some(dir.join("a.processed.txt")));
+        fs::write(dir.join("a.processed.txt"), "").unwrap();
+        assert_eq!(resolve(&policy), Some(dir.join("a.processed.txt")));
+        policy.on_conflict = ConflictStrategy::Skip;
+        assert_eq!(resolve(&policy), None);
+        policy.on_conflict = ConflictStrategy::Rename;
+        assert_eq!(resolve(&policy), Some(dir.join("a.processed (1).txt")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn inputs_sharing_a_destination_conflict() {
+        let mut policy = OutputPolicy {
+            mode: OutputMode::Mirror,
+            dir: Some(PathBuf::from("/nonexistent-output")),
+            ..OutputPolicy::default()
+        };
+        let first = Path::new("/a/x.txt");
+        let second = Path::new("/b/x.txt");
+        let destination = Path::new("/nonexistent-output/x.txt");
+
+        let target = OutputTarget::new(policy.clone(), &[]).unwrap();
+        assert_eq!(target.resolve(first).unwrap().as_deref(), Some(destination));
+        // Retries of the same file keep their destination
+        assert_eq!(target.resolve(first).unwrap().as_deref(), Some(destination));
+        assert!(target.resolve(second).is_err());
+
+        policy.on_conflict = ConflictStrategy::Skip;
+        let target = OutputTarget::new(policy.clone(), &[]).unwrap();
+        target.resolve(first).unwrap();
+        assert_eq!(target.resolve(second).unwrap(), None);
+
+        policy.on_conflict = ConflictStrategy::Rename;
+        let target = OutputTarget::new(policy, &[]).unwrap();
+        target.resolve(first).unwrap();
+        assert_eq!(
+            target.resolve(second).unwrap().as_deref(),
+            Some(Path::new("/nonexistent-output/x (1).txt"))
+        );
+    }
+}
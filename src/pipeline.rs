@@ -0,0 +1,105 @@
+use crate::processor::{self, FileProcessor, Params};
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+
+/// A pipeline step as the user describes it: a processor name and its settings.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepSpec {
+    /// Display name or slug, see `processor::find_processor`.
+    pub processor: String,
+    #[serde(default, skip_serializing_if = "Params::is_empty")]
+    pub params: Params,
+}
+
+impl StepSpec {
+    pub fn new(processor: impl Into<String>) -> Self {
+        StepSpec {
+            processor: processor.into(),
+            params: Params::new(),
+        }
+    }
+}
+
+/// Processors that run one after another on every file. Each step works on
+/// the content the step before it transformed the file into, or on the first
+/// file it produced.
+#[derive(Clone)]
+pub struct Pipeline {
+    steps: Vec<Arc<dyn FileProcessor>>,
+}
+
+impl Pipeline {
+    pub fn new(steps: Vec<Arc<dyn FileProcessor>>) -> Self {
+        assert!(!steps.is_empty(), "A pipeline needs at least one step");
+        Pipeline { steps }
+    }
+
+    /// Looks up and configures the processor of every step.
+    pub fn from_specs(specs: &[StepSpec]) -> Result<Self> {
+        if specs.is_empty() {
+            anyhow::bail!("The pipeline has no steps");
+        }
+        let steps = specs
+            .iter()
+            .enumerate()
+            .map(|(index, spec)| {
+                let processor = processor::find_processor(&spec.processor)
+                    .with_context(|| format!("Unknown processor {:?}", spec.processor))
+                    .and_then(|processor| processor::configure(&processor, &spec.params));
+                processor.with_context(|| format!("Step {}", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Pipeline::new(steps))
+    }
+
+    pub fn steps(&self) -> &[Arc<dyn FileProcessor>] {
+        &self.steps
+    }
+
+    /// Whether more than one processor runs, in which case results record every step.
+    pub fn is_chain(&self) -> bool {
+        self.steps.len() > 1
+    }
+
+    /// Whether any step transforms the content of files.
+    pub fn rewrites_files(&self) -> bool {
+        self.steps.iter().any(|step| step.rewrites_files())
+    }
+
+    /// The step names joined by arrows, or just the processor name for a single step.
+    pub fn name(&self) -> String {
+        let names: Vec<_> = self.steps.iter().map(|step| step.name()).collect();
+        names.join(" → ")
+    }
+
+    /// Runs the `prepare` hook of every step in order, each seeing the file
+    /// list the previous one decided on.
+    pub fn prepare(&self, files: &[PathBuf]) -> Result<(Pipeline, Vec<PathBuf>)> {
+        let mut files = files.to_vec();
+        let mut steps = Vec::with_capacity(self.steps.len());
+        for (index, step) in self.steps.iter().enumerate() {
+            let prepared = step.prepare(&files);
+            let prepared = if self.is_chain() {
+                prepared.with_context(|| self.step_label(index))?
+            } else {
+                prepared?
+            };
+            files = prepared.files;
+            steps.push(prepared.processor.unwrap_or_else(|| step.clone()));
+        }
+        Ok((Pipeline { steps }, files))
+    }
+
+    /// How errors of the step at `index` are introduced, e.g. "Step 2 (Checksum)".
+    pub fn step_label(&self, index: usize) -> String {
+        format!("Step {} ({})", index + 1, self.steps[index].name())
+    }
+}
+
+impl From<Arc<dyn FileProcessor>> for Pipeline {
+    fn from(processor: Arc<dyn FileProcessor>) -> Self {
+        Pipeline::new(vec![processor])
+    }
+}
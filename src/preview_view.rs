@@ -0,0 +1,156 @@
+use crate::results::{FileResult, PendingChange};
+use crate::watch::file_stamp;
+use eframe::egui;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// A file whose changes a dry run held back.
+struct PreviewEntry {
+    file: PathBuf,
+    changes: Vec<PendingChange>,
+    accepted: bool,
+    /// Modification time and size of the file when the preview was made.
+    stamp: Option<(SystemTime, u64)>,
+}
+
+/// What the user decided about the previewed changes.
+pub enum PreviewAction {
+    /// Run the job for real on these files.
+    Apply(Vec<PathBuf>),
+    Discard,
+}
+
+/// Diffs of a dry run, each file accepted or rejected on its own before the
+/// real run.
+pub struct PreviewView {
+    entries: Vec<PreviewEntry>,
+}
+
+impl PreviewView {
+    pub fn new() -> Self {
+        PreviewView { entries: vec![] }
+    }
+
+    /// Takes the files a dry run would change, all of them accepted.
+    pub fn set_results(&mut self, results: &[FileResult]) {
+        self.entries = results
+            .iter()
+            .filter(|result| !result.changes.is_empty())
+            .map(|result| PreviewEntry {
+                file: result.path.clone(),
+                changes: result.changes.clone(),
+                accepted: true,
+                stamp: file_stamp(&result.path),
+            })
+            .collect();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether `file` is still as it was when the preview was made, so
+    /// applying the change gives what the preview showed.
+    pub fn is_unchanged(&self, file: &Path) -> bool {
+        self.entries
+            .iter()
+            .find(|entry| entry.file == file)
+            .is_some_and(|entry| entry.stamp.is_some() && entry.stamp == file_stamp(file))
+    }
+
+    fn accepted_files(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.accepted)
+            .map(|entry| entry.file.clone())
+            .collect()
+    }
+
+    /// Draws the panel and returns the action the user picked, if any.
+    pub fn show(&mut self, ui: &mut egui::Ui, can_apply: bool) -> Option<PreviewAction> {
+        let mut action = None;
+        let accepted = self.accepted_files();
+        ui.horizontal(|ui| {
+            ui.strong(format!("{} file(s) would change", self.entries.len()));
+            if ui.button("Accept all").clicked() {
+                self.entries
+                    .iter_mut()
+                    .for_each(|entry| entry.accepted = true);
+            }
+            if ui.button("Reject all").clicked() {
+                self.entries
+                    .iter_mut()
+                    .for_each(|entry| entry.accepted = false);
+            }
+            let apply_btn = ui
+                .add_enabled(
+                    can_apply && !accepted.is_empty(),
+                    egui::Button::new(format!("Apply {} accepted", accepted.len())),
+                )
+                .on_hover_text(
+                    "Process the accepted files for real, with the settings of the preview",
+                );
+            if apply_btn.clicked() {
+                action = Some(PreviewAction::Apply(accepted));
+            }
+            if ui.button("Discard").clicked() {
+                action = Some(PreviewAction::Discard);
+            }
+        });
+
+        for entry in &mut self.entries {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut entry.accepted, "")
+                    .on_hover_text("Accept this change");
+                egui::CollapsingHeader::new(entry.file.display().to_string())
+                    .id_source(("preview", &entry.file))
+                    .show(ui, |ui| {
+                        for change in &entry.changes {
+                            Self::draw_change(ui, change);
+                        }
+                    });
+            });
+        }
+        action
+    }
+
+    fn draw_change(ui: &mut egui::Ui, change: &PendingChange) {
+        let Some(diff) = &change.diff else {
+            ui.label(format!(
+                "Binary file {} changes from {} to {} bytes",
+                change.path.display(),
+                change.size_before,
+                change.size_after
+            ));
+            return;
+        };
+        let font = egui::FontId::monospace(12.0);
+        let mut job = egui::text::LayoutJob::default();
+        for line in diff.split_inclusive('\n') {
+            let color = if line.starts_with("+++") || line.starts_with("---") {
+                ui.visuals().strong_text_color()
+            } else if line.starts_with('+') {
+                egui::Color32::from_rgb(0, 160, 0)
+            } else if line.starts_with('-') {
+                egui::Color32::from_rgb(200, 60, 60)
+            } else if line.starts_with("@@") {
+                egui::Color32::from_rgb(90, 140, 230)
+            } else {
+                ui.visuals().text_color()
+            };
+            // Make changed line endings visible
+            job.append(
+                &line.replace('\r', "\\r"),
+                0.0,
+                egui::TextFormat::simple(font.clone(), color),
+            );
+        }
+        ui.label(job);
+    }
+}
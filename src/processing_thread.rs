@@ -0,0 +1,1041 @@
+use crate::backup::BackupStore;
+use crate::error::{ErrorCategory, ProcessingError};
+use crate::output::{OutputMode, OutputPolicy, OutputTarget};
+use crate::pipeline::Pipeline;
+use crate::processor::{CancellationToken, FileProcessor, ProcessContext, ProcessOutput};
+use crate::results::{ErrorReport, FileResult, PendingChange, ResultStatus, StepResult};
+use crate::retry::RetryPolicy;
+use crate::worker_pool::{self, WorkerPoolOptions};
+use anyhow::Context as _;
+use eframe::egui;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fs, io,
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How often the watchdog looks for files that overran their timeout.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long files may keep running after the job is cancelled before the job
+/// ends without them.
+const CANCEL_GRACE: Duration = Duration::from_secs(2);
+
+/// Sent as each file moves through the job.
+pub enum ProgressEvent {
+    /// The processor's `prepare` step replaced the file list with these files.
+    Planned(Vec<PathBuf>),
+    Started(PathBuf),
+    Finished(PathBuf, Duration),
+    Failed(PathBuf, Duration),
+    Cancelled(PathBuf),
+    /// The file overran its timeout and was given up on. Its processor may still be running.
+    TimedOut(PathBuf, Duration),
+    /// A processor that had timed out finally returned.
+    ReturnedLate(PathBuf),
+    /// Bytes of the file processed so far, and its total size.
+    Bytes(PathBuf, u64, u64),
+}
+
+impl ProgressEvent {
+    fn for_result(result: &FileResult) -> Self {
+        let path = result.path.clone();
+        match result.status {
+            ResultStatus::Succeeded => ProgressEvent::Finished(path, result.duration),
+            ResultStatus::Failed | ResultStatus::Panicked => {
+                ProgressEvent::Failed(path, result.duration)
+            }
+            ResultStatus::Cancelled => ProgressEvent::Cancelled(path),
+        }
+    }
+}
+
+/// How every file of a job is run.
+#[derive(Clone, Copy)]
+struct JobOptions {
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    dry_run: bool,
+    pool: WorkerPoolOptions,
+}
+
+/// Sent from the pool workers to the thread coordinating the job.
+enum WorkerEvent {
+    Started(usize),
+    Bytes(usize, u64, u64),
+    Done(usize, Box<FileResult>),
+}
+
+/// Everything the worker thread reports back to the owner of the job.
+enum EngineMessage {
+    Progress(ProgressEvent),
+    Finished {
+        state: ThreadState,
+        results: Vec<FileResult>,
+        job_output: Result<ProcessOutput, ErrorReport>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ThreadState {
+    Uninitialized,
+    Initialized,
+    Running,
+    Done,
+    Cancelled,
+}
+
+/// Sends engine messages and wakes the UI up so it can drain them.
+#[derive(Clone)]
+struct Notifier {
+    tx: Sender<EngineMessage>,
+    repaint: Option<egui::Context>,
+}
+
+impl Notifier {
+    fn send(&self, message: EngineMessage) {
+        let _ = self.tx.send(message);
+        if let Some(ctx) = &self.repaint {
+            ctx.request_repaint();
+        }
+    }
+
+    fn progress(&self, event: ProgressEvent) {
+        self.send(EngineMessage::Progress(event));
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown panic payload")
+    }
+}
+
+/// Runs the pipeline on one file, retrying transient failures as `retry` allows.
+fn process_file(
+    pipeline: &Pipeline,
+    file: &Path,
+    ctx: &ProcessContext,
+    retry: &RetryPolicy,
+    target: &OutputTarget,
+) -> FileResult {
+    // Files that haven't started yet are skipped once cancelled
+    if ctx.is_cancelled() {
+        return FileResult::cancelled(file.to_path_buf(), Duration::ZERO);
+    }
+
+    let start = Instant::now();
+    let mut attempt = 1;
+    loop {
+        let mut result = run_pipeline(pipeline, file, ctx, target);
+        let transient = result.status == ResultStatus::Failed
+            && result
+                .error_category()
+                .is_some_and(|category| category.is_transient());
+        if !transient
+            || attempt >= retry.max_attempts
+            || !wait_for_retry(ctx, retry.backoff(attempt))
+        {
+            result.attempts = attempt;
+            result.duration = start.elapsed();
+            return result;
+        }
+        attempt += 1;
+    }
+}
+
+/// Sleeps for `backoff`, returning `false` early if the file gets cancelled meanwhile.
+fn wait_for_retry(ctx: &ProcessContext, backoff: Duration) -> bool {
+    let deadline = Instant::now() + backoff;
+    while !ctx.is_cancelled() {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(50)));
+    }
+    false
+}
+
+/// Runs every step of the pipeline once, then writes the content the steps
+/// transformed the file into where `target` says, or only records the
+/// changes in a dry run. Nothing is written unless all steps succeed.
+fn run_pipeline(
+    pipeline: &Pipeline,
+    file: &Path,
+    ctx: &ProcessContext,
+    target: &OutputTarget,
+) -> FileResult {
+    let start = Instant::now();
+    let mut input = file.to_path_buf();
+    let mut content: Option<Arc<[u8]>> = None;
+    let mut writes = vec![];
+    let mut output = ProcessOutput::new();
+    let mut steps = vec![];
+    for (index, step) in pipeline.steps().iter().enumerate() {
+        let step_ctx = match &content {
+            Some(content) => ctx.clone().with_input(content.clone()),
+            None => ctx.clone(),
+        };
+        let mut result = run_processor(step.as_ref(), &input, &step_ctx);
+        let step_content = result.output.content.take();
+        if pipeline.is_chain() {
+            steps.push(StepResult::new(step.name(), &result));
+        }
+        if result.status != ResultStatus::Succeeded {
+            if let (true, Some(error)) = (pipeline.is_chain(), &mut result.error) {
+                error.add_context(pipeline.step_label(index));
+            }
+            result.path = file.to_path_buf();
+            result.duration = start.elapsed();
+            result.steps = steps;
+            return result;
+        }
+
+        if let Some(step_content) = step_content {
+            content = Some(step_content.into());
+        } else if let Some(produced) = result.output.produced_files.first() {
+            // The next step works on the produced file, the input keeps what was done to it
+            if let Some(content) = content.take() {
+                writes.push((input, content));
+            }
+            input = produced.clone();
+        }
+        output.merge(result.output);
+    }
+    // Outside of in place mode unchanged files get their output too, so
+    // e.g. a mirror folder has every file
+    let unchanged = content.is_none() && writes.is_empty() && input == file;
+    if unchanged && pipeline.rewrites_files() && !target.is_in_place() {
+        let read = fs::read(file)
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Reading {:?}", file));
+        match read {
+            Ok(original) => content = Some(original.into()),
+            Err(e) => {
+                let mut result = FileResult::failed(file.to_path_buf(), start.elapsed(), &e);
+                result.steps = steps;
+                return result;
+            }
+        }
+    }
+    writes.extend(content.map(|content| (input, content)));
+
+    // A file given up on, e.g. after a timeout, is left as it was
+    if ctx.is_cancelled() && !writes.is_empty() {
+        let mut result = FileResult::cancelled(file.to_path_buf(), start.elapsed());
+        result.steps = steps;
+        return result;
+    }
+    let mut changes = vec![];
+    for (path, content) in writes {
+        let destination = match target.resolve(&path) {
+            Ok(Some(destination)) => destination,
+            Ok(None) => {
+                let existing = target.destination(&path);
+                let reason = if existing.exists() {
+                    "already exists"
+                } else {
+                    "is the output of another file"
+                };
+                output.fields.insert(
+                    String::from("not written"),
+                    format!("{} {reason}", existing.display()),
+                );
+                continue;
+            }
+            Err(e) => {
+                let mut result = FileResult::failed(file.to_path_buf(), start.elapsed(), &e);
+                result.steps = steps;
+                return result;
+            }
+        };
+        let written = if ctx.is_dry_run() {
+            preview_change(&destination, &content).map(|change| changes.push(change))
+        } else {
+            target.write(&destination, &content)
+        };
+        if let Err(e) = written {
+            let mut result = FileResult::failed(file.to_path_buf(), start.elapsed(), &e);
+            result.steps = steps;
+            return result;
+        }
+        if destination != path && !ctx.is_dry_run() {
+            output.produced_files.push(destination);
+        }
+    }
+    let mut result = FileResult::succeeded(file.to_path_buf(), start.elapsed(), output);
+    result.steps = steps;
+    result.changes = changes;
+    result
+}
+
+/// What writing `content` to `path` would change.
+fn preview_change(path: &Path, content: &[u8]) -> anyhow::Result<PendingChange> {
+    let before = match fs::read(path) {
+        Ok(before) => before,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => {
+            return Err(ProcessingError::from(e)).with_context(|| format!("Reading {:?}", path))
+        }
+    };
+    Ok(PendingChange::new(path.to_path_buf(), &before, content))
+}
+
+/// Runs a single processor once, turning every way it can end into a result.
+fn run_processor(processor: &dyn FileProcessor, file: &Path, ctx: &ProcessContext) -> FileResult {
+    let path = file.to_path_buf();
+    let start = Instant::now();
+    // A panicking processor must not take the whole job down with it
+    let result = panic::catch_unwind(AssertUnwindSafe(|| processor.process(file, ctx)));
+    let duration = start.elapsed();
+    match result {
+        Err(payload) => FileResult::panicked(path, duration, panic_message(&*payload)),
+        Ok(Ok(output)) => FileResult::succeeded(path, duration, output),
+        Ok(Err(e)) if ErrorCategory::classify(&e) == ErrorCategory::Cancelled => {
+            FileResult::cancelled(path, duration)
+        }
+        Ok(Err(e)) => FileResult::failed(path, duration, &e),
+    }
+}
+
+/// The files of a job, taken one at a time by whichever worker is free.
+struct JobQueue {
+    pipeline: Arc<Pipeline>,
+    target: Arc<OutputTarget>,
+    files: Vec<PathBuf>,
+    contexts: Vec<ProcessContext>,
+    retry: RetryPolicy,
+    next: AtomicUsize,
+}
+
+impl JobQueue {
+    fn take(&self) -> Option<usize> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        (index < self.files.len()).then_some(index)
+    }
+
+    /// Takes every file no worker has started, so none will.
+    fn take_all(&self) -> Range<usize> {
+        let len = self.files.len();
+        self.next.swap(len, Ordering::Relaxed).min(len)..len
+    }
+
+    /// Processes files until none are left, or until the job gives up on the
+    /// one at hand, in which case a replacement worker takes over the rest.
+    fn work(&self, events_tx: &Sender<WorkerEvent>) {
+        while let Some(index) = self.take() {
+            let ctx = &self.contexts[index];
+            if !ctx.is_cancelled() {
+                let _ = events_tx.send(WorkerEvent::Started(index));
+            }
+            let file = &self.files[index];
+            let start = Instant::now();
+            // Writing the output can panic too, not only the processors
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                process_file(&self.pipeline, file, ctx, &self.retry, &self.target)
+            }))
+            .unwrap_or_else(|payload| {
+                FileResult::panicked(file.clone(), start.elapsed(), panic_message(&*payload))
+            });
+            let _ = events_tx.send(WorkerEvent::Done(index, Box::new(result)));
+            if ctx.is_cancelled() {
+                return;
+            }
+        }
+    }
+}
+
+/// Hands every file to the pool and collects the results, giving up on files
+/// that overrun their timeout so a hung processor can't stall the whole job.
+/// Each worker stuck on such a file is replaced by a new one.
+fn coordinate_job(
+    pool: rayon::ThreadPool,
+    pipeline: Arc<Pipeline>,
+    files: &[PathBuf],
+    cancel: &CancellationToken,
+    options: JobOptions,
+    target: Arc<OutputTarget>,
+    notifier: &Notifier,
+) -> Vec<FileResult> {
+    let (events_tx, events_rx) = mpsc::channel();
+    let file_tokens: Vec<_> = files.iter().map(|_| cancel.child()).collect();
+    let contexts = file_tokens
+        .iter()
+        .enumerate()
+        .map(|(index, token)| {
+            let progress_tx = events_tx.clone();
+            ProcessContext::new(token.clone())
+                .with_dry_run(options.dry_run)
+                .with_progress(Arc::new(move |done, total| {
+                    let _ = progress_tx.send(WorkerEvent::Bytes(index, done, total));
+                }))
+        })
+        .collect();
+    let queue = Arc::new(JobQueue {
+        pipeline,
+        target,
+        files: files.to_vec(),
+        contexts,
+        retry: options.retry,
+        next: AtomicUsize::new(0),
+    });
+    for _ in 0..pool.current_num_threads().min(files.len()) {
+        let queue = queue.clone();
+        let events_tx = events_tx.clone();
+        pool.spawn(move || queue.work(&events_tx));
+    }
+
+    let mut results: Vec<Option<FileResult>> = vec![None; files.len()];
+    let mut remaining = files.len();
+    let mut running: HashMap<usize, Instant> = HashMap::new();
+    let mut cancelled_at: Option<Instant> = None;
+    while remaining > 0 {
+        match events_rx.recv_timeout(WATCHDOG_INTERVAL) {
+            Ok(WorkerEvent::Started(index)) => {
+                running.insert(index, Instant::now());
+                notifier.progress(ProgressEvent::Started(files[index].clone()));
+            }
+            Ok(WorkerEvent::Done(index, result)) => {
+                running.remove(&index);
+                if results[index].is_some() {
+                    // Already given up on, keep that result
+                    notifier.progress(ProgressEvent::ReturnedLate(files[index].clone()));
+                    continue;
+                }
+                notifier.progress(ProgressEvent::for_result(&result));
+                results[index] = Some(*result);
+                remaining -= 1;
+            }
+            Ok(WorkerEvent::Bytes(index, done, total)) => {
+                if results[index].is_none() {
+                    notifier.progress(ProgressEvent::Bytes(files[index].clone(), done, total));
+                }
+            }
+            // The coordinator holds a sender itself, so this is only ever a timeout
+            Err(_) => {}
+        }
+
+        if cancel.is_cancelled() && cancelled_at.is_none() {
+            cancelled_at = Some(Instant::now());
+            // Queued files are skipped right away rather than waiting for a worker
+            for index in queue.take_all() {
+                notifier.progress(ProgressEvent::Cancelled(files[index].clone()));
+                results[index] = Some(FileResult::cancelled(files[index].clone(), Duration::ZERO));
+                remaining -= 1;
+            }
+        }
+        let grace_over = cancelled_at.is_some_and(|at| at.elapsed() >= CANCEL_GRACE);
+        let overdue: Vec<_> = running
+            .iter()
+            .map(|(&index, started_at)| (index, started_at.elapsed()))
+            .filter(|&(_, elapsed)| grace_over || options.timeout.is_some_and(|t| elapsed >= t))
+            .collect();
+        for (index, elapsed) in overdue {
+            running.remove(&index);
+            // Ask the processor to stop, but don't wait for it to listen
+            file_tokens[index].cancel();
+            let file = files[index].clone();
+            results[index] = Some(match options.timeout {
+                Some(timeout) if elapsed >= timeout => {
+                    notifier.progress(ProgressEvent::TimedOut(file.clone(), elapsed));
+                    FileResult::timed_out(file, elapsed, timeout)
+                }
+                _ => {
+                    notifier.progress(ProgressEvent::Cancelled(file.clone()));
+                    FileResult::cancelled(file, elapsed)
+                }
+            });
+            remaining -= 1;
+            if cancelled_at.is_some() {
+                continue;
+            }
+
+            let worker_queue = queue.clone();
+            let worker_events_tx = events_tx.clone();
+            let replaced = worker_pool::spawn_replacement(options.pool, move || {
+                worker_queue.work(&worker_events_tx)
+            });
+            if let Err(e) = replaced {
+                for index in queue.take_all() {
+                    let result = FileResult::failed(files[index].clone(), Duration::ZERO, &e);
+                    notifier.progress(ProgressEvent::for_result(&result));
+                    results[index] = Some(result);
+                    remaining -= 1;
+                }
+            }
+        }
+    }
+
+    results
+        .into_iter()
+        .zip(files)
+        .map(|(result, file)| {
+            result.unwrap_or_else(|| {
+                FileResult::panicked(
+                    file.clone(),
+                    Duration::ZERO,
+                    String::from("worker exited without reporting a result"),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Runs the job-wide `finish` hook of every step, each seeing the results of
+/// its own step.
+fn finish_job(
+    pipeline: &Pipeline,
+    results: &[FileResult],
+    target: &OutputTarget,
+) -> Result<ProcessOutput, ErrorReport> {
+    if !pipeline.is_chain() {
+        return finish_step(pipeline.steps()[0].as_ref(), results, target);
+    }
+    let mut output = ProcessOutput::new();
+    for (index, step) in pipeline.steps().iter().enumerate() {
+        let step_results: Vec<_> = results
+            .iter()
+            .map(|result| FileResult {
+                output: result
+                    .steps
+                    .get(index)
+                    .map(|step| step.output.clone())
+                    .unwrap_or_default(),
+                steps: vec![],
+                ..result.clone()
+            })
+            .collect();
+        match finish_step(step.as_ref(), &step_results, target) {
+            Ok(step_output) => output.merge(step_output),
+            Err(mut error) => {
+                error.add_context(pipeline.step_label(index));
+                return Err(error);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Runs one processor's `finish` hook, which must not bring the engine down either.
+fn finish_step(
+    processor: &dyn FileProcessor,
+    results: &[FileResult],
+    target: &OutputTarget,
+) -> Result<ProcessOutput, ErrorReport> {
+    match panic::catch_unwind(AssertUnwindSafe(|| processor.finish(results, target))) {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(e)) => Err(ErrorReport::from_error(&e)),
+        Err(payload) => Err(ErrorReport::from_error(
+            &ProcessingError::Panicked(panic_message(&*payload)).into(),
+        )),
+    }
+}
+
+pub struct FileProcessingThread {
+    pipeline: Pipeline,
+    state: ThreadState,
+    files_to_process: Vec<PathBuf>,
+    processing_results: Vec<FileResult>,
+    job_output: Result<ProcessOutput, ErrorReport>,
+    cancel: CancellationToken,
+    pool_options: WorkerPoolOptions,
+    timeout: Option<Duration>,
+    retry: RetryPolicy,
+    dry_run: bool,
+    output: OutputPolicy,
+    /// Folders the files were found in, see `OutputTarget::destination`.
+    roots: Vec<PathBuf>,
+    backups: Option<BackupStore>,
+    /// Restricts the prepared file list to these files, see `with_only_files`.
+    only_files: Option<Vec<PathBuf>>,
+    worker: Option<JoinHandle<()>>,
+    repaint: Option<egui::Context>,
+    messages_rx: Option<Receiver<EngineMessage>>,
+}
+
+impl FileProcessingThread {
+    /// An engine running `pipeline`, or a single processor, on every file.
+    pub fn new(pipeline: impl Into<Pipeline>) -> Self {
+        FileProcessingThread {
+            pipeline: pipeline.into(),
+            state: ThreadState::Uninitialized,
+            files_to_process: vec![],
+            processing_results: vec![],
+            job_output: Ok(ProcessOutput::default()),
+            cancel: CancellationToken::default(),
+            pool_options: WorkerPoolOptions::default(),
+            timeout: None,
+            retry: RetryPolicy::default(),
+            dry_run: false,
+            output: OutputPolicy::default(),
+            roots: vec![],
+            backups: None,
+            only_files: None,
+            worker: None,
+            repaint: None,
+            messages_rx: None,
+        }
+    }
+
+    /// Requests a repaint of `ctx` whenever the job has news, so the UI
+    /// doesn't have to poll.
+    pub fn with_repaint_context(mut self, ctx: egui::Context) -> Self {
+        self.repaint = Some(ctx);
+        self
+    }
+
+    /// Runs the job on its own thread pool configured by `options`.
+    pub fn with_pool_options(mut self, options: WorkerPoolOptions) -> Self {
+        self.pool_options = options;
+        self
+    }
+
+    /// Gives up on any file that takes longer than `timeout` to process,
+    /// retries included.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retries files that fail with a transient error category.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Lets processors compute their changes without writing anything, see
+    /// `FileResult::changes`. The job-wide `finish` steps are skipped.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Writes transformed files as `policy` says. `roots` are the dropped or
+    /// given folders, which mirroring recreates in the output folder.
+    pub fn with_output_policy(mut self, policy: OutputPolicy, roots: Vec<PathBuf>) -> Self {
+        self.output = policy;
+        self.roots = roots;
+        self
+    }
+
+    /// Keeps a copy of every file before the job first writes to it, and a
+    /// journal of the changes, so the job can be undone.
+    pub fn with_backups(mut self, backups: Option<BackupStore>) -> Self {
+        self.backups = backups;
+        self
+    }
+
+    /// Prepares the job with the whole file list but only processes `files`
+    /// among the prepared ones, e.g. to retry some files of a verify job,
+    /// which needs its manifests to be among the inputs.
+    pub fn with_only_files(mut self, files: Vec<PathBuf>) -> Self {
+        self.only_files = Some(files);
+        self
+    }
+
+    /// A new engine running the same job again: the same pipeline, settings
+    /// and file list, not started yet.
+    pub fn rerun(&self) -> Self {
+        let mut engine = FileProcessingThread::new(self.pipeline.clone())
+            .with_pool_options(self.pool_options)
+            .with_timeout(self.timeout)
+            .with_retry_policy(self.retry)
+            .with_dry_run(self.dry_run)
+            .with_output_policy(self.output.clone(), self.roots.clone())
+            .with_backups(self.backups.clone());
+        engine.repaint = self.repaint.clone();
+        engine.set_file_list(self.files_to_process.clone());
+        engine
+    }
+
+    pub fn file_list(&self) -> &[PathBuf] {
+        &self.files_to_process
+    }
+
+    pub fn set_file_list(&mut self, file_list: Vec<PathBuf>) {
+        self.files_to_process = file_list;
+        self.state = ThreadState::Initialized;
+    }
+
+    pub fn is_in_state(&self, state: ThreadState) -> bool {
+        self.get_state() == state
+    }
+
+    pub fn run(&mut self) {
+        assert!(
+            self.is_in_state(ThreadState::Initialized),
+            "Uninitialized file list, use set_file_list()"
+        );
+
+        self.state = ThreadState::Running;
+
+        let (messages_tx, messages_rx) = mpsc::channel();
+        self.messages_rx = Some(messages_rx);
+        let notifier = Notifier {
+            tx: messages_tx,
+            repaint: self.repaint.clone(),
+        };
+        let files_to_process = self.files_to_process.clone();
+        let pipeline = self.pipeline.clone();
+        let cancel = self.cancel.clone();
+        let options = JobOptions {
+            timeout: self.timeout,
+            retry: self.retry,
+            dry_run: self.dry_run,
+            pool: self.pool_options,
+        };
+        let output = self.output.clone();
+        let roots = self.roots.clone();
+        let backups = self.backups.clone();
+        let only_files: Option<HashSet<_>> = self.only_files.clone().map(HashSet::from_iter);
+        let worker = thread::spawn(move || {
+            let prepared = worker_pool::build_pool(options.pool).and_then(|pool| {
+                let (pipeline, mut files) = pipeline
+                    .prepare(&files_to_process)
+                    .context("Preparing the job")?;
+                let mut target =
+                    OutputTarget::new(output, &roots).context("Invalid output settings")?;
+                if let Some(only_files) = &only_files {
+                    files.retain(|file| only_files.contains(file));
+                }
+                if pipeline.rewrites_files() {
+                    files.retain(|file| !target.is_output(file));
+                }
+                // Job outputs such as manifests are backed up too
+                if !options.dry_run {
+                    if let Some(backups) = backups.filter(BackupStore::is_enabled) {
+                        target = target.with_backup(backups.start_job(&pipeline.name())?);
+                    }
+                }
+                if pipeline.rewrites_files() && !options.dry_run {
+                    target.check_free_space(&files)?;
+                }
+                Ok((pool, pipeline, files, target))
+            });
+            let (pipeline, processing_results, target) = match prepared {
+                Ok((pool, pipeline, files, target)) => {
+                    if files != files_to_process {
+                        notifier.progress(ProgressEvent::Planned(files.clone()));
+                    }
+                    let pipeline = Arc::new(pipeline);
+                    let target = Arc::new(target);
+                    let results = coordinate_job(
+                        pool,
+                        pipeline.clone(),
+                        &files,
+                        &cancel,
+                        options,
+                        target.clone(),
+                        &notifier,
+                    );
+                    (pipeline, results, Some(target))
+                }
+                Err(e) => {
+                    let results = files_to_process
+                        .iter()
+                        .map(|p| FileResult::failed(p.clone(), Duration::ZERO, &e))
+                        .collect();
+                    (Arc::new(pipeline), results, None)
+                }
+            };
+
+            let (state, job_output) = if cancel.is_cancelled() {
+                (ThreadState::Cancelled, Ok(ProcessOutput::default()))
+            } else if let Some(target) = target.filter(|_| !options.dry_run) {
+                let job_output = finish_job(&pipeline, &processing_results, &target);
+                (ThreadState::Done, job_output)
+            } else {
+                (ThreadState::Done, Ok(ProcessOutput::default()))
+            };
+            notifier.send(EngineMessage::Finished {
+                state,
+                results: processing_results,
+                job_output,
+            });
+        });
+        self.worker = Some(worker);
+    }
+
+    fn handle_message(&mut self, message: EngineMessage) -> Option<ProgressEvent> {
+        match message {
+            EngineMessage::Progress(event) => Some(event),
+            EngineMessage::Finished {
+                state,
+                results,
+                job_output,
+            } => {
+                self.state = state;
+                self.processing_results = results;
+                self.job_output = job_output;
+                if let Some(worker) = self.worker.take() {
+                    let _ = worker.join();
+                }
+                None
+            }
+        }
+    }
+
+    /// Finishes the job if the worker thread went away without reporting,
+    /// marking every file as panicked instead of leaving the job running forever.
+    fn handle_disconnect(&mut self) {
+        if !self.is_in_state(ThreadState::Running) {
+            return;
+        }
+        let message = match self.worker.take().map(JoinHandle::join) {
+            Some(Err(payload)) => panic_message(&*payload),
+            _ => String::from("processing thread exited unexpectedly"),
+        };
+        self.processing_results = self
+            .files_to_process
+            .iter()
+            .map(|p| FileResult::panicked(p.clone(), Duration::ZERO, message.clone()))
+            .collect();
+        self.state = ThreadState::Done;
+    }
+
+    /// Drains the messages the worker sent since the last call without
+    /// blocking and returns the progress events among them.
+    pub fn poll_progress(&mut self) -> Vec<ProgressEvent> {
+        let mut events = vec![];
+        loop {
+            let Some(messages_rx) = &self.messages_rx else {
+                return events;
+            };
+            match messages_rx.try_recv() {
+                Ok(message) => events.extend(self.handle_message(message)),
+                Err(TryRecvError::Empty) => return events,
+                Err(TryRecvError::Disconnected) => {
+                    self.handle_disconnect();
+                    return events;
+                }
+            }
+        }
+    }
+
+    /// Blocks until the job started by `run()` has finished.
+    pub fn wait(&mut self) {
+        while self.is_in_state(ThreadState::Running) {
+            let Some(messages_rx) = &self.messages_rx else {
+                return;
+            };
+            match messages_rx.recv() {
+                Ok(message) => {
+                    self.handle_message(message);
+                }
+                Err(_) => self.handle_disconnect(),
+            }
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancel_requested(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.get_state(), ThreadState::Done | ThreadState::Cancelled)
+    }
+
+    pub fn get_state(&self) -> ThreadState {
+        self.state
+    }
+
+    /// What the processor's `finish` step produced, or why it failed.
+    pub fn get_job_output(&self) -> Result<&ProcessOutput, &ErrorReport> {
+        self.job_output.as_ref()
+    }
+
+    /// Per-file results in the order of the file list.
+    pub fn get_results(&self) -> Vec<FileResult> {
+        self.processing_results.clone()
+    }
+
+    /// Every file the finished job wrote: the files it produced, its job
+    /// outputs and the inputs it rewrote in place.
+    pub fn written_files(&self) -> Vec<PathBuf> {
+        if self.dry_run {
+            return vec![];
+        }
+        let rewritten_in_place =
+            self.pipeline.rewrites_files() && self.output.mode == OutputMode::InPlace;
+        let mut written = vec![];
+        for result in &self.processing_results {
+            written.extend(result.output.produced_files.iter().cloned());
+            if rewritten_in_place && result.status == ResultStatus::Succeeded {
+                written.push(result.path.clone());
+            }
+        }
+        if let Ok(output) = &self.job_output {
+            written.extend(output.produced_files.iter().cloned());
+        }
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::sync::Mutex;
+
+    /// Runs the given function on every file.
+    struct TestProcessor<F>(F);
+
+    impl<F> FileProcessor for TestProcessor<F>
+    where
+        F: Fn(&Path, &ProcessContext) -> Result<ProcessOutput> + Send + Sync,
+    {
+        fn name(&self) -> &str {
+            "Test"
+        }
+
+        fn description(&self) -> &str {
+            "Test"
+        }
+
+        fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+            (self.0)(file, ctx)
+        }
+    }
+
+    /// Runs `files` on a single worker and returns their results along with
+    /// the progress events the job sent.
+    fn run_job<F>(
+        process: F,
+        files: &[&str],
+        cancel: &CancellationToken,
+        timeout: Option<Duration>,
+    ) -> (Vec<FileResult>, Vec<ProgressEvent>)
+    where
+        F: Fn(&Path, &ProcessContext) -> Result<ProcessOutput> + Send + Sync + 'static,
+    {
+        let options = JobOptions {
+            timeout,
+            retry: RetryPolicy::default(),
+            dry_run: false,
+            pool: WorkerPoolOptions {
+                threads: 1,
+                background_priority: false,
+            },
+        };
+        let pipeline = Arc::new(Pipeline::new(vec![Arc::new(TestProcessor(process))]));
+        let target = Arc::new(OutputTarget::new(OutputPolicy::default(), &[]).unwrap());
+        let files: Vec<_> = files.iter().map(PathBuf::from).collect();
+        let (tx, rx) = mpsc::channel();
+        let notifier = Notifier { tx, repaint: None };
+        let pool = worker_pool::build_pool(options.pool).unwrap();
+        let results = coordinate_job(pool, pipeline, &files, cancel, options, target, &notifier);
+        let events = rx
+            .try_iter()
+            .filter_map(|message| match message {
+                EngineMessage::Progress(event) => Some(event),
+                EngineMessage::Finished { .. } => None,
+            })
+            .collect();
+        (results, events)
+    }
+
+    fn category(result: &FileResult) -> Option<ErrorCategory> {
+        result.error.as_ref().map(|error| error.category)
+    }
+
+    #[test]
+    fn files_that_overrun_the_timeout_fail_without_stalling_the_job() {
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let (results, _) = run_job(
+            move |_, _| {
+                // Ignores cancellation, like a processor stuck in a system call
+                let _ = blocked.lock().unwrap().recv();
+                Ok(ProcessOutput::new())
+            },
+            &["stuck"],
+            &CancellationToken::default(),
+            Some(Duration::from_millis(200)),
+        );
+        assert_eq!(results.len(), 1);
+        assert!(results[0].status == ResultStatus::Failed);
+        assert!(category(&results[0]) == Some(ErrorCategory::Timeout));
+        drop(release);
+    }
+
+    #[test]
+    fn cancelled_jobs_end_within_the_grace_period() {
+        let (release, blocked) = mpsc::channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let cancel = CancellationToken::default();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            canceller.cancel();
+        });
+        let start = Instant::now();
+        let (results, _) = run_job(
+            move |_, _| {
+                let _ = blocked.lock().unwrap().recv();
+                Ok(ProcessOutput::new())
+            },
+            &["stuck", "queued"],
+            &cancel,
+            None,
+        );
+        assert!(start.elapsed() < CANCEL_GRACE + Duration::from_secs(1));
+        assert!(results
+            .iter()
+            .all(|result| result.status == ResultStatus::Cancelled));
+        drop(release);
+    }
+
+    #[test]
+    fn files_returning_after_their_timeout_keep_the_timeout() {
+        let (returned_tx, returned_rx) = mpsc::channel::<()>();
+        let returned_tx = Mutex::new(returned_tx);
+        let returned_rx = Mutex::new(returned_rx);
+        let (results, events) = run_job(
+            move |file, ctx| {
+                if file == Path::new("slow") {
+                    // Listens to cancellation, but only once the job gave up on it
+                    while !ctx.is_cancelled() {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    let _ = returned_tx.lock().unwrap().send(());
+                } else {
+                    // Keeps the job running until the slow file reported back
+                    let _ = returned_rx.lock().unwrap().recv();
+                    thread::sleep(Duration::from_millis(50));
+                }
+                Ok(ProcessOutput::new())
+            },
+            &["slow", "next"],
+            &CancellationToken::default(),
+            Some(Duration::from_millis(300)),
+        );
+        assert!(category(&results[0]) == Some(ErrorCategory::Timeout));
+        assert!(results[1].status == ResultStatus::Succeeded);
+        assert!(events.iter().any(
+            |event| matches!(event, ProgressEvent::ReturnedLate(path) if path == Path::new("slow"))
+        ));
+    }
+}
@@ -0,0 +1,419 @@
+use crate::checksum::ChecksumProcessor;
+use crate::error::ProcessingError;
+use crate::output::OutputTarget;
+use crate::results::FileResult;
+use crate::text::{NormalizeLineEndingsProcessor, StripTrailingWhitespaceProcessor};
+use crate::verify::VerifyProcessor;
+use anyhow::{Context as _, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Shared flag used to ask a running job, or a single file of it, to stop.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Arc<AtomicBool>>,
+}
+
+impl CancellationToken {
+    /// A token that is cancelled on its own or together with `self`.
+    pub fn child(&self) -> Self {
+        CancellationToken {
+            cancelled: Arc::default(),
+            parent: Some(self.cancelled.clone()),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.load(Ordering::Relaxed))
+    }
+}
+
+/// Called with the bytes done so far and the total size of the current file.
+pub type ProgressCallback = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Per-file state the engine hands to a processor.
+#[derive(Clone, Default)]
+pub struct ProcessContext {
+    pub cancel: CancellationToken,
+    progress: Option<ProgressCallback>,
+    /// Content produced by the previous step of a pipeline, read instead of the file.
+    input: Option<Arc<[u8]>>,
+    dry_run: bool,
+}
+
+impl ProcessContext {
+    pub fn new(cancel: CancellationToken) -> Self {
+        ProcessContext {
+            cancel,
+            progress: None,
+            input: None,
+            dry_run: false,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn with_input(mut self, input: Arc<[u8]>) -> Self {
+        self.input = Some(input);
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// In a dry run processors must not write anything. Content they return
+    /// is shown as a diff instead of being written.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// What the previous step of a pipeline turned the file into, if it changed it.
+    pub fn input(&self) -> Option<&[u8]> {
+        self.input.as_deref()
+    }
+
+    /// Opens the content to process: the output of the previous pipeline
+    /// step if there is one, else `file` itself.
+    pub fn open_input(&self, file: &Path) -> Result<Box<dyn Read + Send>> {
+        match &self.input {
+            Some(input) => Ok(Box::new(io::Cursor::new(input.clone()))),
+            None => {
+                let input = fs::File::open(file)
+                    .map_err(ProcessingError::from)
+                    .with_context(|| format!("Opening {:?}", file))?;
+                Ok(Box::new(input))
+            }
+        }
+    }
+
+    /// Reads the whole content to process, see `open_input`.
+    pub fn read_input(&self, file: &Path) -> Result<Vec<u8>> {
+        match &self.input {
+            Some(input) => Ok(input.to_vec()),
+            None => fs::read(file)
+                .map_err(ProcessingError::from)
+                .with_context(|| format!("Reading {:?}", file)),
+        }
+    }
+
+    /// Lets processors that work through large files in chunks show how far they got.
+    pub fn report_progress(&self, done: u64, total: u64) {
+        if let Some(progress) = &self.progress {
+            progress(done, total);
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Returns `Err(ProcessingError::Cancelled)` once the job has been
+    /// cancelled, so long running processors can bail out with `?` between steps.
+    pub fn check_cancelled(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(ProcessingError::Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Data a processor computed for a single file.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ProcessOutput {
+    /// Named values such as a hash or a count, in the order they were added.
+    pub fields: IndexMap<String, String>,
+    /// Files written by the processor.
+    pub produced_files: Vec<PathBuf>,
+    /// Free-form text for anything that doesn't fit a field.
+    pub text: Option<String>,
+    /// New content for the file, for processors that transform it. The
+    /// engine writes it once every step of the pipeline succeeded.
+    #[serde(skip)]
+    pub content: Option<Vec<u8>>,
+}
+
+impl ProcessOutput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_field(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.fields.insert(key.into(), value.to_string());
+        self
+    }
+
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    pub fn with_content(mut self, content: Vec<u8>) -> Self {
+        self.content = Some(content);
+        self
+    }
+
+    /// Adds the fields, files and text of `other`, whose values win on conflicts.
+    pub fn merge(&mut self, other: ProcessOutput) {
+        self.fields.extend(other.fields);
+        self.produced_files.extend(other.produced_files);
+        if other.text.is_some() {
+            self.text = other.text;
+        }
+    }
+
+    /// One line overview: the fields, then produced files, then the text.
+    pub fn summary(&self) -> String {
+        let mut parts: Vec<_> = self
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .collect();
+        if !self.produced_files.is_empty() {
+            parts.push(format!("{} file(s) written", self.produced_files.len()));
+        }
+        if let Some(text) = &self.text {
+            parts.push(text.lines().next().unwrap_or_default().to_owned());
+        }
+        parts.join(", ")
+    }
+}
+
+/// Processor settings by key, e.g. `algorithms = "sha256,md5"`.
+pub type Params = BTreeMap<String, String>;
+
+/// Describes a setting a processor takes through `FileProcessor::configure`.
+pub struct ParamSpec {
+    pub key: &'static str,
+    pub description: &'static str,
+    pub default: &'static str,
+}
+
+/// What `FileProcessor::prepare` decided the job should run.
+pub struct PreparedJob {
+    pub files: Vec<PathBuf>,
+    /// Takes over from the original processor for this job, e.g. one holding
+    /// what was read from the inputs.
+    pub processor: Option<Arc<dyn FileProcessor>>,
+}
+
+/// A unit of work that can be run on every file of a job.
+///
+/// Implementations are shared between the rayon workers, so they must be
+/// `Send + Sync` and keep any mutable state behind their own locks.
+pub trait FileProcessor: Send + Sync {
+    /// Short name shown in the processor dropdown.
+    fn name(&self) -> &str;
+
+    /// One-line explanation shown as hover text.
+    fn description(&self) -> &str;
+
+    /// Process a single file and report what was computed.
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput>;
+
+    /// Whether `process` returns new content for files, which the engine
+    /// writes as the job's output policy says.
+    fn rewrites_files(&self) -> bool {
+        false
+    }
+
+    /// Settings accepted by `configure`.
+    fn params(&self) -> &'static [ParamSpec] {
+        &[]
+    }
+
+    /// Builds a copy of the processor with `params` applied. Only called with
+    /// keys listed by `params()`, use `processor::configure` to check them.
+    fn configure(&self, _params: &Params) -> Result<Arc<dyn FileProcessor>> {
+        anyhow::bail!("{} takes no parameters", self.name())
+    }
+
+    /// Runs once before the job with every file in it, e.g. to expand
+    /// manifests into the files they list.
+    fn prepare(&self, files: &[PathBuf]) -> Result<PreparedJob> {
+        Ok(PreparedJob {
+            files: files.to_vec(),
+            processor: None,
+        })
+    }
+
+    /// Runs once after every file was processed, e.g. to write a summary file
+    /// through `target`. Skipped when the job was cancelled.
+    fn finish(&self, _results: &[FileResult], _target: &OutputTarget) -> Result<ProcessOutput> {
+        Ok(ProcessOutput::default())
+    }
+}
+
+/// All processors that ship with the application, in dropdown order.
+pub fn builtin_processors() -> Vec<Arc<dyn FileProcessor>> {
+    vec![
+        Arc::new(FileInfoProcessor),
+        Arc::new(LineCountProcessor),
+        Arc::new(SleepProcessor),
+        Arc::new(NormalizeLineEndingsProcessor::default()),
+        Arc::new(StripTrailingWhitespaceProcessor),
+        Arc::new(ChecksumProcessor::default()),
+        Arc::new(VerifyProcessor),
+    ]
+}
+
+/// Applies `params` to `processor`, rejecting keys it doesn't know.
+pub fn configure(
+    processor: &Arc<dyn FileProcessor>,
+    params: &Params,
+) -> Result<Arc<dyn FileProcessor>> {
+    if params.is_empty() {
+        return Ok(processor.clone());
+    }
+    let specs = processor.params();
+    if let Some(key) = params
+        .keys()
+        .find(|key| !specs.iter().any(|spec| spec.key == key.as_str()))
+    {
+        let known: Vec<_> = specs.iter().map(|spec| spec.key).collect();
+        if known.is_empty() {
+            anyhow::bail!("{} takes no parameters, got {key:?}", processor.name());
+        }
+        anyhow::bail!(
+            "Unknown parameter {key:?} for {}, expected one of: {}",
+            processor.name(),
+            known.join(", ")
+        );
+    }
+    processor
+        .configure(params)
+        .with_context(|| format!("Configuring {}", processor.name()))
+}
+
+/// Looks up a built-in processor by its display name or by its slug
+/// (e.g. `line-count` for "Line count").
+pub fn find_processor(name: &str) -> Option<Arc<dyn FileProcessor>> {
+    builtin_processors()
+        .into_iter()
+        .find(|p| p.name().eq_ignore_ascii_case(name) || slug(p.name()) == name)
+}
+
+/// Lowercase, dash separated form of a processor name for use on the command line.
+pub fn slug(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub struct FileInfoProcessor;
+
+impl FileProcessor for FileInfoProcessor {
+    fn name(&self) -> &str {
+        "File info"
+    }
+
+    fn description(&self) -> &str {
+        "Reports the size and last modification time of each file"
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let metadata = fs::metadata(file)
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Reading metadata of {:?}", file))?;
+        if !metadata.is_file() {
+            return Err(ProcessingError::ProcessorSpecific(format!(
+                "{:?} is not a regular file",
+                file
+            ))
+            .into());
+        }
+        let modified = metadata
+            .modified()
+            .map(|t| humantime::format_rfc3339_seconds(t).to_string())
+            .unwrap_or_else(|_| String::from("unknown"));
+        let size = ctx
+            .input()
+            .map_or(metadata.len(), |input| input.len() as u64);
+        Ok(ProcessOutput::new()
+            .with_field("size", size)
+            .with_field("modified", modified))
+    }
+}
+
+pub struct LineCountProcessor;
+
+impl FileProcessor for LineCountProcessor {
+    fn name(&self) -> &str {
+        "Line count"
+    }
+
+    fn description(&self) -> &str {
+        "Counts the lines of each text file"
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let reader = BufReader::new(ctx.open_input(file)?);
+        let mut lines = 0usize;
+        for line in reader.lines() {
+            line.map_err(|e| match e.kind() {
+                io::ErrorKind::InvalidData => {
+                    ProcessingError::InvalidFormat(String::from("not valid UTF-8 text"))
+                }
+                _ => ProcessingError::from(e),
+            })
+            .with_context(|| format!("Reading line {} of {:?}", lines + 1, file))?;
+            lines += 1;
+            if lines % 10_000 == 0 {
+                ctx.check_cancelled()?;
+            }
+        }
+        Ok(ProcessOutput::new().with_field("lines", lines))
+    }
+}
+
+pub struct SleepProcessor;
+
+impl FileProcessor for SleepProcessor {
+    fn name(&self) -> &str {
+        "Sleep (demo)"
+    }
+
+    fn description(&self) -> &str {
+        "Sleeps for one second per file without touching it"
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let deadline = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            ctx.check_cancelled()?;
+            thread::sleep(Duration::from_millis(50));
+        }
+        Ok(
+            ProcessOutput::new()
+                .with_text(format!("Slept thread for 1 second for file {:?}", file)),
+        )
+    }
+}
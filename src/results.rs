@@ -0,0 +1,250 @@
+use crate::error::{ErrorCategory, ProcessingError};
+use crate::processor::ProcessOutput;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use std::{backtrace::BacktraceStatus, path::PathBuf, time::Duration};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ResultStatus {
+    Succeeded,
+    Failed,
+    Panicked,
+    Cancelled,
+}
+
+impl ResultStatus {
+    pub const ALL: [ResultStatus; 4] = [
+        ResultStatus::Succeeded,
+        ResultStatus::Failed,
+        ResultStatus::Panicked,
+        ResultStatus::Cancelled,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResultStatus::Succeeded => "succeeded",
+            ResultStatus::Failed => "failed",
+            ResultStatus::Panicked => "panicked",
+            ResultStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Everything known about why a file failed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ErrorReport {
+    #[serde(default)]
+    pub category: ErrorCategory,
+    /// Every context layer and source error, outermost first, root cause last.
+    pub chain: Vec<String>,
+    /// Only captured when backtraces are enabled, e.g. with `RUST_LIB_BACKTRACE=1`.
+    pub backtrace: Option<String>,
+}
+
+impl ErrorReport {
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        let backtrace = error.backtrace();
+        ErrorReport {
+            category: ErrorCategory::classify(error),
+            chain: error.chain().map(|e| e.to_string()).collect(),
+            backtrace: (backtrace.status() == BacktraceStatus::Captured)
+                .then(|| backtrace.to_string()),
+        }
+    }
+
+    /// Puts `context` in front of the chain, like anyhow's `context` does.
+    pub fn add_context(&mut self, context: String) {
+        self.chain.insert(0, context);
+    }
+
+    /// The outermost message, without the underlying causes.
+    pub fn headline(&self) -> &str {
+        self.chain.first().map_or("", String::as_str)
+    }
+
+    /// The whole chain on one line, in the same form as anyhow's `{:#}`.
+    pub fn message(&self) -> String {
+        self.chain.join(": ")
+    }
+}
+
+/// Outcome of one pipeline step for a single file.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    pub processor: String,
+    pub status: ResultStatus,
+    pub duration: Duration,
+    pub output: ProcessOutput,
+    #[serde(default)]
+    pub error: Option<ErrorReport>,
+}
+
+impl StepResult {
+    pub fn new(processor: &str, result: &FileResult) -> Self {
+        StepResult {
+            processor: processor.to_owned(),
+            status: result.status,
+            duration: result.duration,
+            output: result.output.clone(),
+            error: result.error.clone(),
+        }
+    }
+
+    pub fn detail(&self) -> String {
+        self.error
+            .as_ref()
+            .map_or_else(|| self.output.summary(), ErrorReport::message)
+    }
+}
+
+/// A rewrite the engine held back because the job was a dry run.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PendingChange {
+    pub path: PathBuf,
+    pub size_before: u64,
+    pub size_after: u64,
+    /// Unified diff of the change, `None` when either side isn't UTF-8 text.
+    pub diff: Option<String>,
+}
+
+impl PendingChange {
+    pub fn new(path: PathBuf, before: &[u8], after: &[u8]) -> Self {
+        let diff = match (std::str::from_utf8(before), std::str::from_utf8(after)) {
+            (Ok(before), Ok(after)) => {
+                let name = path.display().to_string();
+                let diff = TextDiff::from_lines(before, after)
+                    .unified_diff()
+                    .context_radius(3)
+                    .header(&name, &name)
+                    .to_string();
+                Some(diff)
+            }
+            _ => None,
+        };
+        PendingChange {
+            path,
+            size_before: before.len() as u64,
+            size_after: after.len() as u64,
+            diff,
+        }
+    }
+}
+
+/// Outcome of processing a single input file.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub status: ResultStatus,
+    pub duration: Duration,
+    pub output: ProcessOutput,
+    #[serde(default)]
+    pub error: Option<ErrorReport>,
+    /// How many times the processor ran, more than one when transient failures were retried.
+    #[serde(default = "one")]
+    pub attempts: u32,
+    /// What every step did when a pipeline of several processors ran, up to
+    /// the first one that didn't succeed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepResult>,
+    /// What a dry run would have written.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<PendingChange>,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl FileResult {
+    pub fn succeeded(path: PathBuf, duration: Duration, output: ProcessOutput) -> Self {
+        FileResult {
+            path,
+            status: ResultStatus::Succeeded,
+            duration,
+            output,
+            error: None,
+            attempts: 1,
+            steps: vec![],
+            changes: vec![],
+        }
+    }
+
+    pub fn failed(path: PathBuf, duration: Duration, error: &anyhow::Error) -> Self {
+        FileResult {
+            path,
+            status: ResultStatus::Failed,
+            duration,
+            output: ProcessOutput::default(),
+            error: Some(ErrorReport::from_error(error)),
+            attempts: 1,
+            steps: vec![],
+            changes: vec![],
+        }
+    }
+
+    pub fn timed_out(path: PathBuf, duration: Duration, timeout: Duration) -> Self {
+        let error = anyhow::Error::from(ProcessingError::Timeout).context(format!(
+            "Still running after the {timeout:?} per-file timeout"
+        ));
+        FileResult::failed(path, duration, &error)
+    }
+
+    pub fn panicked(path: PathBuf, duration: Duration, message: String) -> Self {
+        FileResult {
+            path,
+            status: ResultStatus::Panicked,
+            duration,
+            output: ProcessOutput::default(),
+            error: Some(ErrorReport::from_error(
+                &ProcessingError::Panicked(message).into(),
+            )),
+            attempts: 1,
+            steps: vec![],
+            changes: vec![],
+        }
+    }
+
+    pub fn cancelled(path: PathBuf, duration: Duration) -> Self {
+        FileResult {
+            path,
+            status: ResultStatus::Cancelled,
+            duration,
+            output: ProcessOutput::default(),
+            error: None,
+            attempts: 1,
+            steps: vec![],
+            changes: vec![],
+        }
+    }
+
+    /// Failed in a way that is worth another try, e.g. with "Retry failed".
+    pub fn is_failure(&self) -> bool {
+        matches!(self.status, ResultStatus::Failed | ResultStatus::Panicked)
+    }
+
+    /// The step that stopped a pipeline, numbered from one.
+    pub fn failed_step(&self) -> Option<(usize, &StepResult)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .find(|(_, step)| step.status != ResultStatus::Succeeded)
+            .map(|(index, step)| (index + 1, step))
+    }
+
+    pub fn error_category(&self) -> Option<ErrorCategory> {
+        match self.status {
+            ResultStatus::Cancelled => Some(ErrorCategory::Cancelled),
+            _ => self.error.as_ref().map(|e| e.category),
+        }
+    }
+
+    pub fn error_message(&self) -> Option<String> {
+        self.error.as_ref().map(ErrorReport::message)
+    }
+
+    /// The output summary for successes and the error message for failures.
+    pub fn detail(&self) -> String {
+        self.error_message()
+            .unwrap_or_else(|| self.output.summary())
+    }
+}
@@ -0,0 +1,309 @@
+use crate::error::ErrorCategory;
+use crate::results::{ErrorReport, FileResult, ResultStatus};
+use eframe::egui;
+use std::{cmp::Ordering, collections::BTreeMap};
+
+#[derive(Clone, Copy, PartialEq)]
+enum SortColumn {
+    Status,
+    Path,
+    Duration,
+    Detail,
+}
+
+impl SortColumn {
+    fn compare(&self, a: &FileResult, b: &FileResult) -> Ordering {
+        match self {
+            SortColumn::Status => a.status.cmp(&b.status),
+            SortColumn::Path => a.path.cmp(&b.path),
+            SortColumn::Duration => a.duration.cmp(&b.duration),
+            SortColumn::Detail => a.detail().cmp(&b.detail()),
+        }
+    }
+}
+
+/// Sortable, filterable table of the per-file results of the last job.
+pub struct ResultsView {
+    results: Vec<FileResult>,
+    sort_column: SortColumn,
+    sort_ascending: bool,
+    text_filter: String,
+    status_filter: Option<ResultStatus>,
+    category_filter: Option<ErrorCategory>,
+}
+
+impl ResultsView {
+    pub fn new() -> Self {
+        ResultsView {
+            results: vec![],
+            sort_column: SortColumn::Path,
+            sort_ascending: true,
+            text_filter: String::new(),
+            status_filter: None,
+            category_filter: None,
+        }
+    }
+
+    pub fn results(&self) -> &[FileResult] {
+        &self.results
+    }
+
+    pub fn set_results(&mut self, results: Vec<FileResult>) {
+        self.results = results;
+        self.sort();
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    pub fn count(&self, status: ResultStatus) -> usize {
+        self.results.iter().filter(|r| r.status == status).count()
+    }
+
+    /// Number of unsuccessful files per error category.
+    pub fn category_counts(&self) -> BTreeMap<ErrorCategory, usize> {
+        let mut counts = BTreeMap::new();
+        for category in self.results.iter().filter_map(FileResult::error_category) {
+            *counts.entry(category).or_default() += 1;
+        }
+        counts
+    }
+
+    fn sort(&mut self) {
+        let column = self.sort_column;
+        let ascending = self.sort_ascending;
+        self.results.sort_by(|a, b| {
+            let ordering = column.compare(a, b);
+            if ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+    }
+
+    fn matches_filter(&self, result: &FileResult) -> bool {
+        if self
+            .status_filter
+            .is_some_and(|status| status != result.status)
+            || self
+                .category_filter
+                .is_some_and(|category| result.error_category() != Some(category))
+        {
+            return false;
+        }
+        if self.text_filter.is_empty() {
+            return true;
+        }
+        let needle = self.text_filter.to_lowercase();
+        result
+            .path
+            .to_string_lossy()
+            .to_lowercase()
+            .contains(&needle)
+            || result.detail().to_lowercase().contains(&needle)
+    }
+
+    fn header(&mut self, ui: &mut egui::Ui, column: SortColumn, title: &str) {
+        let label = if self.sort_column == column {
+            let arrow = if self.sort_ascending { "⏶" } else { "⏷" };
+            format!("{title} {arrow}")
+        } else {
+            title.to_owned()
+        };
+        if ui
+            .add(egui::Button::new(egui::RichText::new(label).strong()).frame(false))
+            .clicked()
+        {
+            if self.sort_column == column {
+                self.sort_ascending = !self.sort_ascending;
+            } else {
+                self.sort_column = column;
+                self.sort_ascending = true;
+            }
+            self.sort();
+        }
+    }
+
+    fn category_color(ui: &egui::Ui, category: ErrorCategory) -> egui::Color32 {
+        match category {
+            ErrorCategory::Io => egui::Color32::from_rgb(230, 120, 40),
+            ErrorCategory::PermissionDenied => egui::Color32::from_rgb(200, 60, 160),
+            ErrorCategory::NotFound => egui::Color32::from_rgb(170, 130, 0),
+            ErrorCategory::InvalidFormat => egui::Color32::from_rgb(200, 80, 80),
+            ErrorCategory::ChecksumMismatch => egui::Color32::from_rgb(230, 50, 50),
+            ErrorCategory::Timeout => egui::Color32::from_rgb(90, 140, 230),
+            ErrorCategory::Cancelled => ui.visuals().warn_fg_color,
+            ErrorCategory::Panicked => ui.visuals().error_fg_color,
+            ErrorCategory::ProcessorSpecific => egui::Color32::from_rgb(220, 90, 40),
+        }
+    }
+
+    pub fn result_color(ui: &egui::Ui, result: &FileResult) -> egui::Color32 {
+        match result.error_category() {
+            Some(category) => Self::category_color(ui, category),
+            None => egui::Color32::from_rgb(0, 160, 0),
+        }
+    }
+
+    fn status_text(result: &FileResult) -> String {
+        let status = match (result.status, result.error_category()) {
+            (ResultStatus::Failed, Some(category)) => {
+                format!("{} ({})", result.status.label(), category.label())
+            }
+            _ => result.status.label().to_owned(),
+        };
+        let status = match result.failed_step() {
+            Some((number, _)) => format!("{status} at step {number}"),
+            None => status,
+        };
+        if result.attempts > 1 {
+            format!("{status} after {} attempts", result.attempts)
+        } else {
+            status
+        }
+    }
+
+    /// What each pipeline step did, shown when hovering the status.
+    fn steps_tooltip(ui: &mut egui::Ui, result: &FileResult) {
+        egui::Grid::new("pipeline steps")
+            .num_columns(4)
+            .show(ui, |ui| {
+                for (index, step) in result.steps.iter().enumerate() {
+                    ui.label(format!("{}.", index + 1));
+                    ui.strong(&step.processor);
+                    ui.label(step.status.label());
+                    ui.label(step.detail());
+                    ui.end_row();
+                }
+            });
+    }
+
+    fn draw_category_counts(&mut self, ui: &mut egui::Ui) {
+        let counts = self.category_counts();
+        if counts.is_empty() {
+            return;
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Failures:");
+            for (category, count) in counts {
+                let text = egui::RichText::new(format!("{count} {}", category.label()))
+                    .color(Self::category_color(ui, category));
+                let selected = self.category_filter == Some(category);
+                if ui
+                    .selectable_label(selected, text)
+                    .on_hover_text("Show only this kind of failure")
+                    .clicked()
+                {
+                    self.category_filter = if selected { None } else { Some(category) };
+                }
+            }
+        });
+    }
+
+    fn error_details(
+        ui: &mut egui::Ui,
+        result: &FileResult,
+        error: &ErrorReport,
+        color: egui::Color32,
+    ) {
+        let title = egui::RichText::new(error.headline()).color(color);
+        egui::CollapsingHeader::new(title)
+            .id_source(&result.path)
+            .show(ui, |ui| {
+                for (depth, cause) in error.chain.iter().enumerate().skip(1) {
+                    ui.label(format!("{depth}: {cause}"));
+                }
+                if let Some(backtrace) = &error.backtrace {
+                    egui::CollapsingHeader::new("Backtrace")
+                        .id_source((&result.path, "backtrace"))
+                        .show(ui, |ui| {
+                            ui.label(egui::RichText::new(backtrace).monospace().small());
+                        });
+                }
+                if ui.small_button("Copy").clicked() {
+                    let mut text = error.chain.join("\nCaused by: ");
+                    if let Some(backtrace) = &error.backtrace {
+                        text = format!("{text}\n\n{backtrace}");
+                    }
+                    ui.output_mut(|o| o.copied_text = text);
+                }
+            });
+    }
+
+    fn output_label(ui: &mut egui::Ui, result: &FileResult) {
+        let output = &result.output;
+        let response = ui.label(output.summary());
+        if output.fields.is_empty() && output.produced_files.is_empty() && output.text.is_none() {
+            return;
+        }
+        response.on_hover_ui(|ui| {
+            egui::Grid::new("output fields")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    for (key, value) in &output.fields {
+                        ui.strong(key);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                    for file in &output.produced_files {
+                        ui.strong("wrote");
+                        ui.label(file.display().to_string());
+                        ui.end_row();
+                    }
+                });
+            if let Some(text) = &output.text {
+                ui.label(text);
+            }
+        });
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        self.draw_category_counts(ui);
+        ui.horizontal(|ui| {
+            ui.label("Filter");
+            ui.text_edit_singleline(&mut self.text_filter);
+
+            let selected = self.status_filter.map_or("All", |s| s.label());
+            egui::ComboBox::from_id_source("status filter")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.status_filter, None, "All");
+                    for status in ResultStatus::ALL {
+                        ui.selectable_value(&mut self.status_filter, Some(status), status.label());
+                    }
+                });
+        });
+
+        egui::Grid::new("results grid")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                self.header(ui, SortColumn::Status, "Status");
+                self.header(ui, SortColumn::Path, "File");
+                self.header(ui, SortColumn::Duration, "Duration");
+                self.header(ui, SortColumn::Detail, "Output");
+                ui.end_row();
+
+                for result in self.results.iter().filter(|r| self.matches_filter(r)) {
+                    let color = Self::result_color(ui, result);
+                    let status = ui.colored_label(color, Self::status_text(result));
+                    if !result.steps.is_empty() {
+                        status.on_hover_ui(|ui| Self::steps_tooltip(ui, result));
+                    }
+                    ui.label(result.path.display().to_string());
+                    ui.label(format!("{:.2?}", result.duration));
+                    match &result.error {
+                        Some(error) => Self::error_details(ui, result, error, color),
+                        None => Self::output_label(ui, result),
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{Context as _, Result};
+use eframe::egui;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+use walkdir::WalkDir;
+
+/// Controls how dropped directories are expanded into files.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// `None` recurses without limit, `Some(1)` only takes the direct children.
+    pub max_depth: Option<usize>,
+    pub follow_symlinks: bool,
+    /// Glob patterns matched against the path relative to the scanned folder.
+    /// An empty list includes everything.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl ScanOptions {
+    /// Splits a user supplied list of patterns on commas and whitespace.
+    pub fn parse_patterns(patterns: &str) -> Vec<String> {
+        patterns
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+pub struct ScanResult {
+    pub root: PathBuf,
+    pub files: Result<Vec<PathBuf>>,
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("Invalid glob {pattern:?}"))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// The include and exclude patterns of `ScanOptions`, compiled once.
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    pub fn new(options: &ScanOptions) -> Result<Self> {
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&options.include)?)
+        };
+        Ok(PathFilter {
+            include,
+            exclude: build_glob_set(&options.exclude)?,
+        })
+    }
+
+    /// Whether a path relative to the scanned folder should be taken.
+    pub fn matches(&self, relative: &Path) -> bool {
+        self.include.as_ref().map_or(true, |i| i.is_match(relative))
+            && !self.exclude.is_match(relative)
+    }
+}
+
+/// Recursively collects the files below `root`, sorted by path.
+pub fn scan_directory(root: &Path, options: &ScanOptions) -> Result<Vec<PathBuf>> {
+    let filter = PathFilter::new(options)?;
+
+    let mut walker = WalkDir::new(root)
+        .follow_links(options.follow_symlinks)
+        .sort_by_file_name();
+    if let Some(max_depth) = options.max_depth {
+        walker = walker.max_depth(max_depth);
+    }
+
+    let mut files = vec![];
+    for entry in walker {
+        // Unreadable entries below the root are skipped rather than failing the whole scan
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if e.depth() == 0 => {
+                return Err(e).with_context(|| format!("Scanning {:?}", root));
+            }
+            Err(_) => continue,
+        };
+        // Without following, symlinks show up as their own entry type and are skipped
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if !filter.matches(relative) {
+            continue;
+        }
+        files.push(entry.into_path());
+    }
+    Ok(files)
+}
+
+/// Expands a mix of files and directories, keeping plain files as they are.
+pub fn expand_paths(paths: &[PathBuf], options: &ScanOptions) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            files.extend(scan_directory(path, options)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Scans directories on background threads so large trees don't block the UI.
+pub struct DirectoryScanner {
+    results_tx: Sender<ScanResult>,
+    results_rx: Receiver<ScanResult>,
+    repaint: Option<egui::Context>,
+}
+
+impl DirectoryScanner {
+    pub fn new() -> Self {
+        let (results_tx, results_rx) = mpsc::channel();
+        DirectoryScanner {
+            results_tx,
+            results_rx,
+            repaint: None,
+        }
+    }
+
+    /// Requests a repaint of `ctx` when a scan completes.
+    pub fn with_repaint_context(mut self, ctx: egui::Context) -> Self {
+        self.repaint = Some(ctx);
+        self
+    }
+
+    pub fn scan(&self, root: PathBuf, options: ScanOptions) {
+        let results_tx = self.results_tx.clone();
+        let repaint = self.repaint.clone();
+        thread::spawn(move || {
+            let files = scan_directory(&root, &options);
+            let _ = results_tx.send(ScanResult { root, files });
+            if let Some(ctx) = repaint {
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    pub fn poll_results(&self) -> Vec<ScanResult> {
+        self.results_rx.try_iter().collect()
+    }
+}
@@ -0,0 +1,224 @@
+use crate::error::ProcessingError;
+use crate::processor::{FileProcessor, ParamSpec, Params, ProcessContext, ProcessOutput};
+use anyhow::Result;
+use std::{path::Path, sync::Arc};
+
+const LINE_ENDING_PARAMS: &[ParamSpec] = &[ParamSpec {
+    key: "ending",
+    description: "Line ending to convert to, `lf` or `crlf`",
+    default: "lf",
+}];
+
+/// Reads the content to transform, refusing files that don't look like text.
+fn read_text(file: &Path, ctx: &ProcessContext) -> Result<Vec<u8>> {
+    let content = ctx.read_input(file)?;
+    if content.contains(&0) {
+        return Err(ProcessingError::InvalidFormat(String::from(
+            "contains NUL bytes, looks like a binary file",
+        ))
+        .into());
+    }
+    Ok(content)
+}
+
+/// Splits `content` into lines, each with its own line ending if it has one.
+fn lines_with_endings(content: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    content.split_inclusive(|&b| b == b'\n').map(|line| {
+        let body_len = if line.ends_with(b"\r\n") {
+            line.len() - 2
+        } else if line.ends_with(b"\n") {
+            line.len() - 1
+        } else {
+            line.len()
+        };
+        line.split_at(body_len)
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
+}
+
+/// Converts every line ending of a text file to LF or CRLF.
+#[derive(Default)]
+pub struct NormalizeLineEndingsProcessor {
+    ending: LineEnding,
+}
+
+impl FileProcessor for NormalizeLineEndingsProcessor {
+    fn name(&self) -> &str {
+        "Normalize line endings"
+    }
+
+    fn description(&self) -> &str {
+        "Rewrites text files so every line ends in LF, or CRLF if configured"
+    }
+
+    fn rewrites_files(&self) -> bool {
+        true
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let content = read_text(file, ctx)?;
+        let ending = self.ending.as_bytes();
+        let mut normalized = Vec::with_capacity(content.len());
+        let mut changed = 0usize;
+        for (body, line_ending) in lines_with_endings(&content) {
+            normalized.extend_from_slice(body);
+            if line_ending.is_empty() {
+                continue;
+            }
+            if line_ending != ending {
+                changed += 1;
+            }
+            normalized.extend_from_slice(ending);
+        }
+
+        let output = ProcessOutput::new().with_field("line endings changed", changed);
+        if changed == 0 {
+            return Ok(output);
+        }
+        Ok(output.with_content(normalized))
+    }
+
+    fn params(&self) -> &'static [ParamSpec] {
+        LINE_ENDING_PARAMS
+    }
+
+    fn configure(&self, params: &Params) -> Result<Arc<dyn FileProcessor>> {
+        let ending = match params.get("ending").map(|e| e.trim().to_ascii_lowercase()) {
+            None => LineEnding::Lf,
+            Some(ending) => match ending.as_str() {
+                "lf" => LineEnding::Lf,
+                "crlf" => LineEnding::CrLf,
+                _ => anyhow::bail!("Unknown line ending {ending:?}, expected lf or crlf"),
+            },
+        };
+        Ok(Arc::new(NormalizeLineEndingsProcessor { ending }))
+    }
+}
+
+/// Removes spaces and tabs from the end of every line of a text file.
+pub struct StripTrailingWhitespaceProcessor;
+
+impl FileProcessor for StripTrailingWhitespaceProcessor {
+    fn name(&self) -> &str {
+        "Strip trailing whitespace"
+    }
+
+    fn description(&self) -> &str {
+        "Rewrites text files without the spaces and tabs at the end of their lines"
+    }
+
+    fn rewrites_files(&self) -> bool {
+        true
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let content = read_text(file, ctx)?;
+        let mut stripped = Vec::with_capacity(content.len());
+        let mut changed = 0usize;
+        for (body, line_ending) in lines_with_endings(&content) {
+            let kept = body.len()
+                - body
+                    .iter()
+                    .rev()
+                    .take_while(|&&b| b == b' ' || b == b'\t')
+                    .count();
+            if kept < body.len() {
+                changed += 1;
+            }
+            stripped.extend_from_slice(&body[..kept]);
+            stripped.extend_from_slice(line_ending);
+        }
+
+        let output = ProcessOutput::new().with_field("lines stripped", changed);
+        if changed == 0 {
+            return Ok(output);
+        }
+        Ok(output.with_content(stripped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCategory;
+
+    /// Runs `processor` on `content` and returns the new content, if any.
+    fn process(processor: &dyn FileProcessor, content: &[u8]) -> Result<Option<Vec<u8>>> {
+        let ctx = ProcessContext::default().with_input(Arc::from(content));
+        let output = processor.process(Path::new("test.txt"), &ctx)?;
+        Ok(output.content)
+    }
+
+    #[test]
+    fn lines_keep_their_own_endings() {
+        let lines: Vec<_> = lines_with_endings(b"a\r\nb\nc\rd\n\ne").collect();
+        assert_eq!(
+            lines,
+            [
+                (&b"a"[..], &b"\r\n"[..]),
+                (b"b", b"\n"),
+                (b"c\rd", b"\n"),
+                (b"", b"\n"),
+                (b"e", b""),
+            ]
+        );
+        assert_eq!(lines_with_endings(b"").count(), 0);
+    }
+
+    #[test]
+    fn normalizes_mixed_line_endings() {
+        let lf = NormalizeLineEndingsProcessor::default();
+        let crlf = NormalizeLineEndingsProcessor {
+            ending: LineEnding::CrLf,
+        };
+        let mixed = b"a\r\nb\nc";
+        assert_eq!(process(&lf, mixed).unwrap().unwrap(), b"a\nb\nc");
+        assert_eq!(process(&crlf, mixed).unwrap().unwrap(), b"a\r\nb\r\nc");
+        // A lone CR is not a line ending
+        assert_eq!(process(&crlf, b"a\rb\n").unwrap().unwrap(), b"a\rb\r\n");
+    }
+
+    #[test]
+    fn normalized_files_are_left_unchanged() {
+        let lf = NormalizeLineEndingsProcessor::default();
+        assert_eq!(process(&lf, b"a\nb\n").unwrap(), None);
+        assert_eq!(process(&lf, b"a\rb").unwrap(), None);
+        assert_eq!(process(&lf, b"").unwrap(), None);
+    }
+
+    #[test]
+    fn strips_trailing_whitespace() {
+        let strip = StripTrailingWhitespaceProcessor;
+        assert_eq!(
+            process(&strip, b"a \r\nb\t\n  \nc  ").unwrap().unwrap(),
+            b"a\r\nb\n\nc"
+        );
+        assert_eq!(process(&strip, b"a\r\n b\n\n").unwrap(), None);
+    }
+
+    #[test]
+    fn binary_files_are_refused() {
+        for processor in [
+            &NormalizeLineEndingsProcessor::default() as &dyn FileProcessor,
+            &StripTrailingWhitespaceProcessor,
+        ] {
+            let error = process(processor, b"a\r\n\0b ").unwrap_err();
+            assert!(ErrorCategory::classify(&error) == ErrorCategory::InvalidFormat);
+        }
+    }
+}
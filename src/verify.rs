@@ -0,0 +1,346 @@
+use crate::checksum::{self, HashAlgorithm};
+use crate::error::ProcessingError;
+use crate::processor::{FileProcessor, PreparedJob, ProcessContext, ProcessOutput};
+use anyhow::{Context as _, Result};
+use indexmap::IndexMap;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+/// Extensions of single-algorithm manifests such as `delivery.sha256`.
+const MANIFEST_EXTENSIONS: [&str; 10] = [
+    "sha256",
+    "sha256sum",
+    "sha1",
+    "sha1sum",
+    "md5",
+    "md5sum",
+    "b3",
+    "b3sum",
+    "blake3",
+    "crc32",
+];
+
+/// A digest a manifest expects a file to have.
+struct Expected {
+    algorithm: HashAlgorithm,
+    digest: String,
+    manifest: PathBuf,
+}
+
+/// Checks files against the `sha256sum` or BSD style manifests among the
+/// inputs. Files listed in a manifest but not dropped are looked up next to
+/// it, dropped files that no manifest lists are reported as extra.
+pub struct VerifyProcessor;
+
+impl VerifyProcessor {
+    fn is_manifest(path: &Path) -> bool {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            return false;
+        };
+        name.ends_with("SUMS")
+            || path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| MANIFEST_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+    }
+
+    /// The algorithm a manifest name implies, e.g. SHA-256 for `SHA256SUMS` or `x.sha256`.
+    fn algorithm_from_name(path: &Path) -> Option<HashAlgorithm> {
+        let name = path.file_name()?.to_str()?;
+        let hint = match name.strip_suffix("SUMS") {
+            Some(prefix) => prefix,
+            None => path.extension()?.to_str()?,
+        };
+        let hint = hint.to_ascii_lowercase();
+        let hint = hint.strip_suffix("sum").unwrap_or(&hint);
+        match hint {
+            "b3" => Some(HashAlgorithm::Blake3),
+            hint => HashAlgorithm::parse(hint).ok(),
+        }
+    }
+
+    fn read_manifest(manifest: &Path) -> Result<Vec<(PathBuf, Expected)>> {
+        let content = fs::read_to_string(manifest)
+            .map_err(ProcessingError::from)
+            .with_context(|| format!("Reading manifest {:?}", manifest))?;
+        let algorithm = Self::algorithm_from_name(manifest);
+        let folder = manifest.parent().unwrap_or(Path::new(""));
+
+        let mut entries = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (algorithm, digest, name) = parse_line(line, algorithm)
+                .map_err(ProcessingError::InvalidFormat)
+                .with_context(|| format!("Line {} of {:?}", index + 1, manifest))?;
+            let expected = Expected {
+                algorithm,
+                digest: digest.to_ascii_lowercase(),
+                manifest: manifest.to_path_buf(),
+            };
+            entries.push((normalize(&folder.join(name)), expected));
+        }
+        Ok(entries)
+    }
+}
+
+/// Splits a manifest line into algorithm, digest and file name. Understands
+/// `sha256sum` lines (`<digest>  <name>`, `*` marking binary mode, a leading
+/// `\` for escaped names) and BSD tagged lines (`SHA256 (<name>) = <digest>`).
+pub fn parse_line(
+    line: &str,
+    algorithm: Option<HashAlgorithm>,
+) -> Result<(HashAlgorithm, String, String), String> {
+    if let Some((tag, rest)) = line.split_once(" (") {
+        if let Some((name, digest)) = rest.rsplit_once(") = ") {
+            let algorithm = HashAlgorithm::parse(tag).map_err(|e| e.to_string())?;
+            return Ok((algorithm, check_digest(digest)?, name.to_owned()));
+        }
+    }
+
+    let (escaped, line) = match line.strip_prefix('\\') {
+        Some(line) => (true, line),
+        None => (false, line),
+    };
+    let Some((digest, name)) = line.split_once(' ') else {
+        return Err(String::from("expected a digest and a file name"));
+    };
+    // The second separator character is ' ' for text and '*' for binary mode
+    let name = name
+        .strip_prefix(' ')
+        .or_else(|| name.strip_prefix('*'))
+        .unwrap_or(name);
+    let name = if escaped {
+        unescape(name)
+    } else {
+        name.to_owned()
+    };
+    let digest = check_digest(digest)?;
+    let algorithm = match algorithm {
+        Some(algorithm) => algorithm,
+        None => match digest.len() {
+            32 => HashAlgorithm::Md5,
+            40 => HashAlgorithm::Sha1,
+            64 => HashAlgorithm::Sha256,
+            _ => return Err(format!("can't tell the algorithm of digest {digest:?}")),
+        },
+    };
+    Ok((algorithm, digest, name))
+}
+
+fn check_digest(digest: &str) -> Result<String, String> {
+    if digest.is_empty() || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("{digest:?} is not a hex digest"));
+    }
+    Ok(digest.to_owned())
+}
+
+/// Undoes the escaping `sha256sum` applies to names with backslashes or newlines.
+fn unescape(name: &str) -> String {
+    let mut unescaped = String::with_capacity(name.len());
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Drops `.` components so paths from manifests and dropped paths compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| *c != Component::CurDir)
+        .collect()
+}
+
+impl FileProcessor for VerifyProcessor {
+    fn name(&self) -> &str {
+        "Verify checksums"
+    }
+
+    fn description(&self) -> &str {
+        "Checks files against SHA256SUMS, *.md5 and similar manifests dropped with them or on their own"
+    }
+
+    fn process(&self, _file: &Path, _ctx: &ProcessContext) -> Result<ProcessOutput> {
+        // Jobs always run the `VerifyJob` built by `prepare`
+        Err(ProcessingError::ProcessorSpecific(String::from("no manifest was read")).into())
+    }
+
+    fn prepare(&self, files: &[PathBuf]) -> Result<PreparedJob> {
+        let (manifests, others): (Vec<_>, Vec<_>) =
+            files.iter().partition(|file| Self::is_manifest(file));
+        if manifests.is_empty() {
+            return Err(ProcessingError::ProcessorSpecific(String::from(
+                "no checksum manifest such as SHA256SUMS or *.md5 among the files",
+            ))
+            .into());
+        }
+
+        let mut expected: IndexMap<PathBuf, Vec<Expected>> = IndexMap::new();
+        for manifest in &manifests {
+            for (file, entry) in Self::read_manifest(manifest)? {
+                expected.entry(file).or_default().push(entry);
+            }
+        }
+        let manifests: HashSet<_> = manifests.iter().map(|m| normalize(m)).collect();
+        let mut job_files: Vec<_> = expected.keys().cloned().collect();
+        for file in others {
+            let file = normalize(file);
+            if !expected.contains_key(&file) && !manifests.contains(&file) {
+                job_files.push(file);
+            }
+        }
+
+        Ok(PreparedJob {
+            files: job_files,
+            processor: Some(Arc::new(VerifyJob { expected })),
+        })
+    }
+}
+
+/// The verify processor for one job, holding what its manifests expect.
+struct VerifyJob {
+    expected: IndexMap<PathBuf, Vec<Expected>>,
+}
+
+impl FileProcessor for VerifyJob {
+    fn name(&self) -> &str {
+        VerifyProcessor.name()
+    }
+
+    fn description(&self) -> &str {
+        VerifyProcessor.description()
+    }
+
+    fn process(&self, file: &Path, ctx: &ProcessContext) -> Result<ProcessOutput> {
+        let Some(expected) = self.expected.get(file) else {
+            return Ok(ProcessOutput::new().with_field("verify", "extra"));
+        };
+        if let Err(e) = fs::metadata(file) {
+            if e.kind() == io::ErrorKind::NotFound {
+                return Err(ProcessingError::NotFound)
+                    .with_context(|| format!("Missing, listed in {:?}", expected[0].manifest));
+            }
+        }
+
+        let mut algorithms = vec![];
+        for entry in expected {
+            if !algorithms.contains(&entry.algorithm) {
+                algorithms.push(entry.algorithm);
+            }
+        }
+        let digests = checksum::hash_file(file, &algorithms, ctx)?;
+        let mut output = ProcessOutput::new().with_field("verify", "OK");
+        for (algorithm, actual) in algorithms.iter().zip(&digests) {
+            output = output.with_field(algorithm.name(), actual);
+        }
+        for entry in expected {
+            let index = algorithms
+                .iter()
+                .position(|a| *a == entry.algorithm)
+                .unwrap_or_default();
+            if digests[index] != entry.digest {
+                return Err(ProcessingError::ChecksumMismatch {
+                    algorithm: entry.algorithm.name().to_owned(),
+                    expected: entry.digest.clone(),
+                    actual: digests[index].clone(),
+                })
+                .with_context(|| format!("Doesn't match {:?}", entry.manifest));
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256_EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const MD5_EMPTY: &str = "d41d8cd98f00b204e9800998ecf8427e";
+
+    #[test]
+    fn parses_gnu_lines() {
+        let line = format!("{SHA256_EMPTY}  notes.txt");
+        assert_eq!(
+            parse_line(&line, None).unwrap(),
+            (
+                HashAlgorithm::Sha256,
+                SHA256_EMPTY.to_owned(),
+                String::from("notes.txt")
+            )
+        );
+
+        // Binary mode, and a name starting with a space
+        let line = format!("{MD5_EMPTY} * spaced.bin");
+        let (algorithm, _, name) = parse_line(&line, None).unwrap();
+        assert_eq!(algorithm, HashAlgorithm::Md5);
+        assert_eq!(name, " spaced.bin");
+    }
+
+    #[test]
+    fn manifest_name_decides_the_algorithm() {
+        // BLAKE3 digests are as long as SHA-256 ones
+        let line = format!("{SHA256_EMPTY}  a.txt");
+        let (algorithm, _, _) = parse_line(&line, Some(HashAlgorithm::Blake3)).unwrap();
+        assert_eq!(algorithm, HashAlgorithm::Blake3);
+    }
+
+    #[test]
+    fn parses_bsd_lines() {
+        let line = format!("SHA256 (dir/a (1).txt) = {SHA256_EMPTY}");
+        assert_eq!(
+            parse_line(&line, Some(HashAlgorithm::Md5)).unwrap(),
+            (
+                HashAlgorithm::Sha256,
+                SHA256_EMPTY.to_owned(),
+                String::from("dir/a (1).txt")
+            )
+        );
+        let line = format!("MD5 (a.txt) = {MD5_EMPTY}");
+        assert_eq!(parse_line(&line, None).unwrap().0, HashAlgorithm::Md5);
+    }
+
+    #[test]
+    fn parses_escaped_names() {
+        let line = format!("\\{SHA256_EMPTY}  line\\nbreak\\\\slash");
+        let (_, _, name) = parse_line(&line, None).unwrap();
+        assert_eq!(name, "line\nbreak\\slash");
+
+        // Without the leading backslash names are taken as they are
+        let line = format!("{SHA256_EMPTY}  back\\nslash");
+        let (_, _, name) = parse_line(&line, None).unwrap();
+        assert_eq!(name, "back\\nslash");
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse_line("not-hex  a.txt", None).is_err());
+        assert!(parse_line(SHA256_EMPTY, None).is_err());
+        assert!(parse_line("abcd  a.txt", None).is_err());
+        assert!(parse_line(&format!("FOO (a.txt) = {MD5_EMPTY}"), None).is_err());
+    }
+
+    #[test]
+    fn unescapes_like_sha256sum() {
+        assert_eq!(unescape("a\\nb\\rc"), "a\nb\rc");
+        assert_eq!(unescape("a\\\\b"), "a\\b");
+        assert_eq!(unescape("trailing\\"), "trailing\\");
+        assert_eq!(unescape("plain"), "plain");
+    }
+}
@@ -0,0 +1,278 @@
+use crate::scan::{PathFilter, ScanOptions};
+use anyhow::{Context as _, Result};
+use eframe::egui;
+use notify::{Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Controls how watched folders are monitored.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchOptions {
+    /// A file is only processed once it saw no changes for this long.
+    pub settle: Duration,
+    /// Skip the native backend (inotify on Linux) and poll instead.
+    pub force_polling: bool,
+    pub poll_interval: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            settle: Duration::from_secs(2),
+            force_polling: false,
+            poll_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchBackend {
+    Native,
+    Polling,
+}
+
+impl WatchBackend {
+    pub fn label(&self) -> &'static str {
+        match self {
+            WatchBackend::Native => "native",
+            WatchBackend::Polling => "polling",
+        }
+    }
+}
+
+/// Watches folders for new and modified files and reports each of them once
+/// it stopped changing.
+pub struct FolderWatcher {
+    // Kept alive for as long as events should keep coming
+    _watcher: Box<dyn Watcher + Send>,
+    backend: WatchBackend,
+    events_rx: Receiver<notify::Result<Event>>,
+    roots: Vec<PathBuf>,
+    scan_options: ScanOptions,
+    filter: PathFilter,
+    settle: Duration,
+    /// Files that changed recently, with the time of their last change.
+    pending: HashMap<PathBuf, Instant>,
+    /// Files a job wrote, with their modification time and size right after.
+    written: HashMap<PathBuf, (SystemTime, u64)>,
+    errors: Vec<String>,
+}
+
+impl FolderWatcher {
+    /// Starts watching `roots` recursively, only reporting files the scan
+    /// options would have picked up.
+    ///
+    /// Requests a repaint of `repaint` on every file system event.
+    pub fn new(
+        roots: Vec<PathBuf>,
+        scan_options: &ScanOptions,
+        options: WatchOptions,
+        repaint: Option<egui::Context>,
+    ) -> Result<Self> {
+        let filter = PathFilter::new(scan_options)?;
+        // Events carry absolute paths, which have to line up with the roots
+        let roots = roots
+            .iter()
+            .map(|root| {
+                root.canonicalize()
+                    .with_context(|| format!("Watching {:?}", root))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let (events_tx, events_rx) = mpsc::channel();
+        let handler = move |event| {
+            let _ = events_tx.send(event);
+            if let Some(ctx) = &repaint {
+                ctx.request_repaint();
+            }
+        };
+
+        let mut errors = vec![];
+        let native = if options.force_polling {
+            None
+        } else {
+            // inotify can fail to start or run out of watches, polling works everywhere
+            match Self::watch_all(
+                RecommendedWatcher::new(handler.clone(), notify::Config::default()),
+                &roots,
+            ) {
+                Ok(watcher) => Some(watcher),
+                Err(e) => {
+                    errors.push(format!("Falling back to polling: {e:#}"));
+                    None
+                }
+            }
+        };
+        let (watcher, backend) = match native {
+            Some(watcher) => (watcher, WatchBackend::Native),
+            None => {
+                let config = notify::Config::default().with_poll_interval(options.poll_interval);
+                let watcher = Self::watch_all(PollWatcher::new(handler, config), &roots)?;
+                (watcher, WatchBackend::Polling)
+            }
+        };
+
+        Ok(FolderWatcher {
+            _watcher: watcher,
+            backend,
+            events_rx,
+            roots,
+            scan_options: scan_options.clone(),
+            filter,
+            settle: options.settle,
+            pending: HashMap::new(),
+            written: HashMap::new(),
+            errors,
+        })
+    }
+
+    fn watch_all<W: Watcher + Send + 'static>(
+        watcher: notify::Result<W>,
+        roots: &[PathBuf],
+    ) -> Result<Box<dyn Watcher + Send>> {
+        let mut watcher = watcher.context("Starting the file watcher")?;
+        for root in roots {
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .with_context(|| format!("Watching {:?}", root))?;
+        }
+        Ok(Box::new(watcher))
+    }
+
+    pub fn backend(&self) -> WatchBackend {
+        self.backend
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Whether some files changed but haven't settled yet.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Ignores the changes a job made to `files` for as long as they stay the
+    /// way it left them, so a job writing into a watched folder doesn't
+    /// trigger the next one.
+    pub fn ignore_written(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+        for file in files {
+            let file = file.canonicalize().unwrap_or(file);
+            if let Some(stamp) = file_stamp(&file) {
+                self.written.insert(file, stamp);
+            }
+        }
+    }
+
+    /// Whether `path` is still exactly as a job wrote it, see `ignore_written`.
+    pub fn is_own_write(&mut self, path: &Path) -> bool {
+        let Some(stamp) = self.written.get(path) else {
+            return false;
+        };
+        if file_stamp(path).as_ref() == Some(stamp) {
+            return true;
+        }
+        self.written.remove(path);
+        false
+    }
+
+    /// Errors and warnings reported by the watcher since the last call.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns the files that settled since the last call without blocking.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        while let Ok(event) = self.events_rx.try_recv() {
+            self.handle_event(event);
+        }
+        self.take_settled()
+    }
+
+    /// Blocks until at least one file settled.
+    pub fn wait(&mut self) -> Result<Vec<PathBuf>> {
+        loop {
+            let timeout = self.next_settle_in().unwrap_or(Duration::from_secs(3600));
+            match self.events_rx.recv_timeout(timeout) {
+                Ok(event) => self.handle_event(event),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("The file watcher stopped"),
+            }
+            let settled = self.take_settled();
+            if !settled.is_empty() {
+                return Ok(settled);
+            }
+        }
+    }
+
+    /// Time until the next pending file settles, if any are pending.
+    pub fn next_settle_in(&self) -> Option<Duration> {
+        self.pending
+            .values()
+            .map(|changed| self.settle.saturating_sub(changed.elapsed()))
+            .min()
+    }
+
+    fn handle_event(&mut self, event: notify::Result<Event>) {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                self.errors.push(e.to_string());
+                return;
+            }
+        };
+        match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) => {
+                for path in event.paths {
+                    if self.is_wanted(&path) {
+                        self.pending.insert(path, Instant::now());
+                    }
+                }
+            }
+            EventKind::Remove(_) => {
+                for path in &event.paths {
+                    self.pending.remove(path);
+                }
+            }
+            EventKind::Access(_) | EventKind::Any | EventKind::Other => {}
+        }
+    }
+
+    fn is_wanted(&self, path: &Path) -> bool {
+        let Some(root) = self.roots.iter().find(|root| path.starts_with(root)) else {
+            return false;
+        };
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let depth = relative.components().count();
+        self.scan_options.max_depth.map_or(true, |max| depth <= max)
+            && self.filter.matches(relative)
+    }
+
+    fn take_settled(&mut self) -> Vec<PathBuf> {
+        let mut settled: Vec<_> = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= self.settle)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled.sort();
+        for path in &settled {
+            self.pending.remove(path);
+        }
+        // Files renamed or deleted before settling, and directories, are not processed
+        settled.retain(|path| path.is_file() && !self.is_own_write(path));
+        settled
+    }
+}
+
+/// Modification time and size of a file, to tell whether it changed since.
+pub fn file_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -0,0 +1,232 @@
+use crate::pipeline::Pipeline;
+use crate::processing_thread::FileProcessingThread;
+use crate::results::FileResult;
+use crate::results_view::ResultsView;
+use crate::watch::{FolderWatcher, WatchOptions};
+use eframe::egui;
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+/// Oldest log entries are dropped beyond this many.
+const LOG_LIMIT: usize = 1000;
+
+enum LogEntry {
+    Result(SystemTime, Box<FileResult>),
+    Message(SystemTime, String),
+}
+
+/// Folders being watched and the job processing the files that settled in them.
+struct WatchSession {
+    watcher: FolderWatcher,
+    pipeline: Pipeline,
+    queue: Vec<PathBuf>,
+    engine: Option<FileProcessingThread>,
+}
+
+/// What the user asked for in the watch panel.
+pub enum WatchAction {
+    Start,
+    Stop,
+}
+
+/// Watch-folder controls and the live log of what was processed.
+pub struct WatchView {
+    pub options: WatchOptions,
+    session: Option<WatchSession>,
+    log: VecDeque<LogEntry>,
+}
+
+impl WatchView {
+    pub fn new() -> Self {
+        WatchView {
+            options: WatchOptions::default(),
+            session: None,
+            log: VecDeque::new(),
+        }
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn start(&mut self, watcher: FolderWatcher, pipeline: Pipeline) {
+        self.push(LogEntry::Message(
+            SystemTime::now(),
+            format!(
+                "Watching {} folder(s) ({}) with {}",
+                watcher.roots().len(),
+                watcher.backend().label(),
+                pipeline.name()
+            ),
+        ));
+        self.session = Some(WatchSession {
+            watcher,
+            pipeline,
+            queue: vec![],
+            engine: None,
+        });
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(session) = self.session.take() {
+            if let Some(engine) = &session.engine {
+                engine.cancel();
+            }
+            self.push(LogEntry::Message(
+                SystemTime::now(),
+                String::from("Stopped watching"),
+            ));
+        }
+    }
+
+    pub fn log_message(&mut self, message: String) {
+        self.push(LogEntry::Message(SystemTime::now(), message));
+    }
+
+    fn push(&mut self, entry: LogEntry) {
+        if self.log.len() == LOG_LIMIT {
+            self.log.pop_front();
+        }
+        self.log.push_back(entry);
+    }
+
+    /// Queues settled files, collects finished results and starts the next
+    /// job through `new_engine` once the previous one is done.
+    pub fn update(
+        &mut self,
+        ctx: &egui::Context,
+        new_engine: impl FnOnce(Pipeline) -> FileProcessingThread,
+    ) {
+        let Some(session) = &mut self.session else {
+            return;
+        };
+        let now = SystemTime::now();
+        let mut entries: Vec<_> = session
+            .watcher
+            .take_errors()
+            .into_iter()
+            .map(|e| LogEntry::Message(now, e))
+            .collect();
+        for file in session.watcher.poll() {
+            if !session.queue.contains(&file) {
+                session.queue.push(file);
+            }
+        }
+
+        if let Some(engine) = &mut session.engine {
+            engine.poll_progress();
+            if engine.is_finished() {
+                let now = SystemTime::now();
+                entries.extend(
+                    engine
+                        .get_results()
+                        .into_iter()
+                        .map(|result| LogEntry::Result(now, Box::new(result))),
+                );
+                session.watcher.ignore_written(engine.written_files());
+                let watcher = &mut session.watcher;
+                session.queue.retain(|file| !watcher.is_own_write(file));
+                session.engine = None;
+            }
+        }
+        if session.engine.is_none() && !session.queue.is_empty() {
+            let mut engine = new_engine(session.pipeline.clone());
+            engine.set_file_list(std::mem::take(&mut session.queue));
+            engine.run();
+            session.engine = Some(engine);
+        }
+
+        // Files only settle once nothing happens, so nothing would wake the UI up
+        if let Some(settle_in) = session.watcher.next_settle_in() {
+            ctx.request_repaint_after(settle_in);
+        }
+        for entry in entries {
+            self.push(entry);
+        }
+    }
+
+    /// Draws the panel and returns the action the user picked, if any.
+    pub fn show(&mut self, ui: &mut egui::Ui, can_start: bool) -> Option<WatchAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            match &self.session {
+                None => {
+                    let start_btn = ui
+                        .add_enabled(can_start, egui::Button::new("Start watching"))
+                        .on_hover_text("Process new and changed files in the dropped folders");
+                    if start_btn.clicked() {
+                        action = Some(WatchAction::Start);
+                    }
+                }
+                Some(session) => {
+                    if ui.button("Stop watching").clicked() {
+                        action = Some(WatchAction::Stop);
+                    }
+                    if session.engine.is_some() {
+                        ui.spinner();
+                    }
+                    let mut status = format!("{} queued", session.queue.len());
+                    if session.watcher.has_pending() {
+                        status.push_str(", waiting for files to settle");
+                    }
+                    ui.weak(status);
+                }
+            }
+            if ui
+                .add_enabled(!self.log.is_empty(), egui::Button::new("Clear log"))
+                .clicked()
+            {
+                self.log.clear();
+            }
+        });
+
+        ui.add_enabled_ui(self.session.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Settle time");
+                let mut secs = self.options.settle.as_secs_f64();
+                let drag = egui::DragValue::new(&mut secs)
+                    .clamp_range(0.0..=3600.0)
+                    .speed(0.1)
+                    .suffix(" s");
+                if ui
+                    .add(drag)
+                    .on_hover_text("Files are processed once they didn't change for this long")
+                    .changed()
+                {
+                    self.options.settle = Duration::from_secs_f64(secs);
+                }
+                ui.checkbox(&mut self.options.force_polling, "Poll")
+                    .on_hover_text("Look for changes periodically instead of using inotify");
+            });
+        });
+
+        egui::ScrollArea::vertical()
+            .id_source("watch log")
+            .max_height(150.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for entry in &self.log {
+                    Self::draw_entry(ui, entry);
+                }
+            });
+        action
+    }
+
+    fn draw_entry(ui: &mut egui::Ui, entry: &LogEntry) {
+        ui.horizontal(|ui| match entry {
+            LogEntry::Result(at, result) => {
+                ui.weak(humantime::format_rfc3339_seconds(*at).to_string());
+                ui.colored_label(ResultsView::result_color(ui, result), result.status.label());
+                ui.label(result.path.display().to_string())
+                    .on_hover_text(result.detail());
+            }
+            LogEntry::Message(at, message) => {
+                ui.weak(humantime::format_rfc3339_seconds(*at).to_string());
+                ui.label(message);
+            }
+        });
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+/// How many threads a job may use and how politely they run.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkerPoolOptions {
+    /// `0` uses one thread per logical core.
+    pub threads: usize,
+    /// Lowers the OS scheduling priority of the workers so the UI and other
+    /// programs stay responsive. Only has an effect on Linux.
+    pub background_priority: bool,
+}
+
+impl WorkerPoolOptions {
+    pub fn available_threads() -> usize {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+}
+
+pub fn build_pool(options: WorkerPoolOptions) -> Result<rayon::ThreadPool> {
+    pool_builder(options, |index| format!("file-worker-{index}"))
+        .build()
+        .context("Starting the worker thread pool")
+}
+
+/// Starts a worker outside the pool, standing in for a pool worker that is
+/// stuck on a file the job gave up on. It gets a one thread pool of its own,
+/// so the parallel work of processors stays off the global pool.
+pub fn spawn_replacement(
+    options: WorkerPoolOptions,
+    work: impl FnOnce() + Send + 'static,
+) -> Result<()> {
+    let options = WorkerPoolOptions {
+        threads: 1,
+        ..options
+    };
+    let pool = pool_builder(options, |_| String::from("file-worker-replacement"))
+        .build()
+        .context("Starting a replacement worker thread")?;
+    // The thread keeps running the work after the pool handle is dropped
+    pool.spawn(work);
+    Ok(())
+}
+
+fn pool_builder(
+    options: WorkerPoolOptions,
+    thread_name: impl FnMut(usize) -> String + 'static,
+) -> rayon::ThreadPoolBuilder {
+    let background_priority = options.background_priority;
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .thread_name(thread_name)
+        // Rayon aborts on panics in spawned jobs unless they are handled. Jobs
+        // catch their own per file, this only keeps anything else from
+        // taking the application down
+        .panic_handler(|_| {})
+        .start_handler(move |_| {
+            if background_priority {
+                lower_current_thread_priority();
+            }
+        })
+}
+
+#[cfg(target_os = "linux")]
+fn lower_current_thread_priority() {
+    // On Linux the nice value is per thread, so this only affects the calling worker
+    // SAFETY: plain syscalls without pointers, failure is harmless and ignored
+    unsafe {
+        let tid = libc::gettid();
+        libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, 10);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_current_thread_priority() {}